@@ -1,15 +1,15 @@
-use std::fmt::{Debug, Display};
+use std::fmt::{Debug, Display, Write};
 use std::hash::Hash;
 use std::mem;
 use std::ops::{Deref, DerefMut};
 
 use crate::board_repr::*;
 use crate::magic_bitboards::MAGIC_MOVER;
-use crate::moving::{Move, MoveType, Unmove};
+use crate::moving::{Move, MoveType, NullUnmove, Unmove};
 use crate::piece::{Piece, PieceType, Side};
 use crate::position::Position;
 use crate::search_data::{CheckPath, PinState};
-use crate::search_masks::{KING_MASKS, KNIGHT_MASKS, choose_home_rook, choose_pawn_take_mask};
+use crate::search_masks::{KING_MASKS, KNIGHT_MASKS, choose_pawn_take_mask};
 use crate::zobrist::*;
 
 use PieceType::*;
@@ -20,6 +20,7 @@ use arrayvec::ArrayVec;
 pub struct SearchBoard {
     pub state: BoardState,
     pub halfmove_clock: u8,
+    pub fullmove_number: u16,
 }
 macro_rules! allies {
     ($side: ident, $state: ident) => {
@@ -127,17 +128,21 @@ impl SearchBoard {
 
         let piece = mov.piece_type();
 
-        self.state
-            .zobrist
-            .update(piece.with_side(ally_side), mov.from());
-        self.state
-            .zobrist
-            .update(piece.with_side(ally_side), mov.to());
-        self.state.board.board[*mov.to() as usize] =
-            mem::replace(&mut self.state.board.board[*mov.from() as usize], None);
-
-        *allies!(ally_side, self).get_bitboard_mut(piece) ^=
-            mov.from().as_mask() | mov.to().as_mask();
+        if let MoveType::LongCastle | MoveType::ShortCastle = mov.move_type {
+            self.castle(ally_side, mov.move_type == MoveType::LongCastle);
+        } else {
+            self.state
+                .zobrist
+                .update(piece.with_side(ally_side), mov.from());
+            self.state
+                .zobrist
+                .update(piece.with_side(ally_side), mov.to());
+            self.state.board.board[*mov.to() as usize] =
+                mem::replace(&mut self.state.board.board[*mov.from() as usize], None);
+
+            *allies!(ally_side, self).get_bitboard_mut(piece) ^=
+                mov.from().as_mask() | mov.to().as_mask();
+        }
 
         if let Some(taken) = mov.take {
             *self.get_bitboard_mut(taken) ^= mov.to().as_mask();
@@ -153,20 +158,6 @@ impl SearchBoard {
                 allies!(ally_side, self).state[PAWN] ^= mov.to().as_mask();
                 *allies!(ally_side, self).get_bitboard_mut(p) ^= mov.to().as_mask();
             }
-            MoveType::LongCastle => {
-                self.state.board.board[(3 + ally_side.home_y() * 8) as usize] = mem::replace(
-                    &mut self.state.board.board[(0 + ally_side.home_y() * 8) as usize],
-                    None,
-                );
-                allies!(ally_side, self).state[ROOK] ^= 0x9 << (ally_side.home_y() * 8);
-            }
-            MoveType::ShortCastle => {
-                self.state.board.board[(5 + ally_side.home_y() * 8) as usize] = mem::replace(
-                    &mut self.state.board.board[(7 + ally_side.home_y() * 8) as usize],
-                    None,
-                );
-                allies!(ally_side, self).state[ROOK] ^= 0xa0 << (ally_side.home_y() * 8);
-            }
             MoveType::EnPassant => {
                 let ep_pawn = mov.to().with_y(ally_side.pers_y(4)).unwrap();
                 increment_halfmove = false;
@@ -191,7 +182,7 @@ impl SearchBoard {
 
         self.state.zobrist.switch_side();
 
-        let ally_home_rook = choose_home_rook(ally_side);
+        let ally_home_rook = self.home_rooks(ally_side);
         if mov.from() == ally_home_rook[0] {
             if self.state.side_castle_rights(ally_side).0 {
                 self.state.zobrist.update_long_castle(ally_side);
@@ -203,7 +194,7 @@ impl SearchBoard {
             }
             self.side_castle_rights_mut(ally_side).1 = false;
         }
-        let enemy_home_rook = choose_home_rook(enemy_side);
+        let enemy_home_rook = self.home_rooks(enemy_side);
         if mov.to() == enemy_home_rook[0] {
             if self.state.side_castle_rights(enemy_side).0 {
                 self.state.zobrist.update_long_castle(enemy_side);
@@ -217,7 +208,10 @@ impl SearchBoard {
         }
 
         if piece == PieceType::King {
-            *self.side_king_mut(ally_side) = mov.to;
+            // castling already put the king where it belongs
+            if mov.move_type == MoveType::Normal(King) {
+                *self.side_king_mut(ally_side) = mov.to;
+            }
             if self.state.side_castle_rights(ally_side).0 {
                 self.side_castle_rights_mut(ally_side).0 = false;
                 self.state.zobrist.update_long_castle(ally_side);
@@ -228,25 +222,54 @@ impl SearchBoard {
             }
         }
 
+        if ally_side == Side::Black {
+            self.fullmove_number += 1;
+        }
         self.state.side = self.state.side.opposite();
     }
 
+    /// Passes the turn without moving, for null-move pruning. Never legal in check.
+    pub fn make_null(&mut self) -> NullUnmove {
+        let unmove = NullUnmove {
+            en_passant_square: self.state.en_passant_square,
+            zobrist: self.state.zobrist,
+        };
+        let side = self.state.side;
+        self.state
+            .zobrist
+            .update_ep_square(side, self.state.en_passant_square, None);
+        self.state.en_passant_square = None;
+        self.state.zobrist.switch_side();
+        self.state.side = side.opposite();
+        unmove
+    }
+
+    pub fn unmake_null(&mut self, unmove: NullUnmove) {
+        self.state.side = self.state.side.opposite();
+        self.state.en_passant_square = unmove.en_passant_square;
+        self.state.zobrist = unmove.zobrist;
+    }
+
     pub fn unmake(&mut self, unmove: Unmove) {
         self.state.side = self.state.side.opposite();
         let ally_side = self.state.side;
         let mov = unmove.mov;
 
         let piece = mov.piece_type();
-        *allies!(ally_side, self).get_bitboard_mut(piece) ^=
-            mov.from().as_mask() | mov.to().as_mask();
-
-        if let Some(taken) = mov.take {
-            *self.get_bitboard_mut(taken) ^= mov.to().as_mask();
-            self.state.board.board[*mov.from() as usize] =
-                mem::replace(&mut self.state.board.board[*mov.to() as usize], Some(taken));
+        if let MoveType::LongCastle | MoveType::ShortCastle = mov.move_type {
+            self.uncastle(ally_side, mov.move_type == MoveType::LongCastle, mov.from());
         } else {
-            self.state.board.board[*mov.from() as usize] =
-                mem::replace(&mut self.state.board.board[*mov.to() as usize], None)
+            *allies!(ally_side, self).get_bitboard_mut(piece) ^=
+                mov.from().as_mask() | mov.to().as_mask();
+
+            if let Some(taken) = mov.take {
+                *self.get_bitboard_mut(taken) ^= mov.to().as_mask();
+                self.state.board.board[*mov.from() as usize] =
+                    mem::replace(&mut self.state.board.board[*mov.to() as usize], Some(taken));
+            } else {
+                self.state.board.board[*mov.from() as usize] =
+                    mem::replace(&mut self.state.board.board[*mov.to() as usize], None)
+            }
         }
         match mov.move_type {
             MoveType::Promotion(p) => {
@@ -254,20 +277,6 @@ impl SearchBoard {
                 allies!(ally_side, self).state[PAWN] ^= mov.to().as_mask();
                 *allies!(ally_side, self).get_bitboard_mut(p) ^= mov.to().as_mask();
             }
-            MoveType::LongCastle => {
-                self.state.board.board[(0 + ally_side.home_y() * 8) as usize] = mem::replace(
-                    &mut self.state.board.board[(3 + ally_side.home_y() * 8) as usize],
-                    None,
-                );
-                allies!(ally_side, self).state[ROOK] ^= 0x9 << (ally_side.home_y() * 8);
-            }
-            MoveType::ShortCastle => {
-                self.state.board.board[(7 + ally_side.home_y() * 8) as usize] = mem::replace(
-                    &mut self.state.board.board[(5 + ally_side.home_y() * 8) as usize],
-                    None,
-                );
-                allies!(ally_side, self).state[ROOK] ^= 0xa0 << (ally_side.home_y() * 8);
-            }
             MoveType::EnPassant => {
                 // the pawn that is taken
                 let ep_pawn = mov.to().with_y(ally_side.pers_y(4)).unwrap();
@@ -289,20 +298,185 @@ impl SearchBoard {
         self.state.black_castling = unmove.black_castling;
         self.state.zobrist = unmove.zobrist;
         self.halfmove_clock = unmove.halfmove_clock;
+        if ally_side == Side::Black {
+            self.fullmove_number -= 1;
+        }
+    }
+
+    /// Moves the king and the rook of a castling move. In Chess960 their squares may overlap,
+    /// so both pieces are lifted before either is put down and the bitboards are XORed.
+    fn castle(&mut self, side: Side, long: bool) {
+        let king_from = self.side_king(side);
+        let (rook_from, king_to, rook_to) = self.castling_squares(side, long);
+        let (king, rook) = (King.with_side(side), Rook.with_side(side));
+
+        self.state.board.board[*king_from as usize] = None;
+        self.state.board.board[*rook_from as usize] = None;
+        self.state.board.board[*king_to as usize] = Some(king);
+        self.state.board.board[*rook_to as usize] = Some(rook);
+        allies!(side, self).state[KING] ^= king_from.as_mask() ^ king_to.as_mask();
+        allies!(side, self).state[ROOK] ^= rook_from.as_mask() ^ rook_to.as_mask();
+        for (piece, square) in [
+            (king, king_from),
+            (king, king_to),
+            (rook, rook_from),
+            (rook, rook_to),
+        ] {
+            self.state.zobrist.update(piece, square);
+        }
+        *self.side_king_mut(side) = king_to;
+    }
+    fn uncastle(&mut self, side: Side, long: bool, king_from: Position) {
+        let (rook_from, king_to, rook_to) = self.castling_squares(side, long);
+
+        self.state.board.board[*king_to as usize] = None;
+        self.state.board.board[*rook_to as usize] = None;
+        self.state.board.board[*king_from as usize] = Some(King.with_side(side));
+        self.state.board.board[*rook_from as usize] = Some(Rook.with_side(side));
+        allies!(side, self).state[KING] ^= king_from.as_mask() ^ king_to.as_mask();
+        allies!(side, self).state[ROOK] ^= rook_from.as_mask() ^ rook_to.as_mask();
     }
 
     pub fn from_fen(fen: &str) -> Self {
-        let state = BoardState::from_fen(fen);
-        let halfmove_clock = fen
-            .split(" ")
-            .nth(4)
-            .expect("Invalid FEN")
-            .parse()
-            .expect("Invalid FEN");
-        Self {
+        Self::try_from_fen(fen).expect("Invalid FEN")
+    }
+    pub fn try_from_fen(fen: &str) -> Option<Self> {
+        let state = BoardState::try_from_fen(fen)?;
+        let mut counters = fen.split_whitespace().skip(4);
+        let halfmove_clock = match counters.next() {
+            Some(clock) => clock.parse().ok()?,
+            None => 0,
+        };
+        let fullmove_number = match counters.next() {
+            Some(number) => number.parse().ok()?,
+            None => 1,
+        };
+        Some(Self {
             halfmove_clock,
+            fullmove_number,
             state,
+        })
+    }
+    /// One of the 960 starting positions of Chess960 by its standard (Scharnagl) number,
+    /// 518 being the usual starting position
+    pub fn chess960_start(index: u16) -> Option<Self> {
+        if index >= 960 {
+            return None;
         }
+        let mut back_rank = [None; 8];
+        let index = index as usize;
+        // the light squared bishop goes on b, d, f or h, the dark squared one on a, c, e or g
+        back_rank[index % 4 * 2 + 1] = Some(Bishop);
+        back_rank[index / 4 % 4 * 2] = Some(Bishop);
+        let mut place = |piece, nth: usize| {
+            let file = (0..8).filter(|x| back_rank[*x].is_none()).nth(nth).unwrap();
+            back_rank[file] = Some(piece);
+        };
+        place(Queen, index / 16 % 6);
+        // the knights take two of the five squares that are left, the second one counted
+        // after the first is placed
+        let (first, second) = [
+            (0, 0),
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (1, 1),
+            (1, 2),
+            (1, 3),
+            (2, 2),
+            (2, 3),
+            (3, 3),
+        ][index / 96];
+        place(Knight, first);
+        place(Knight, second);
+        // rook, king and rook in the remaining squares, in that order
+        place(Rook, 0);
+        place(King, 0);
+        place(Rook, 0);
+
+        let back_rank: String = back_rank
+            .iter()
+            .map(|piece| piece.unwrap().as_char().to_ascii_lowercase())
+            .collect();
+        Self::try_from_fen(&format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+            back_rank,
+            back_rank.to_ascii_uppercase()
+        ))
+    }
+
+    /// X-FEN, which is plain FEN unless a Chess960 castling rook needs its file spelled out
+    pub fn to_fen(&self) -> String {
+        self.write_fen(false)
+    }
+    /// Shredder-FEN, the castling rights are always given as the files of the rooks
+    pub fn to_shredder_fen(&self) -> String {
+        self.write_fen(true)
+    }
+    fn write_fen(&self, shredder: bool) -> String {
+        let mut fen = String::new();
+        for y in (0..8).rev() {
+            let mut empty = 0;
+            for x in 0..8 {
+                let Some(piece) = self.board.board[y * 8 + x] else {
+                    empty += 1;
+                    continue;
+                };
+                if empty > 0 {
+                    write!(fen, "{}", empty).unwrap();
+                    empty = 0;
+                }
+                // as_char() has the cases the other way around
+                fen.push(match piece.side() {
+                    Side::White => piece.role().as_char(),
+                    Side::Black => piece.role().as_char().to_ascii_lowercase(),
+                });
+            }
+            if empty > 0 {
+                write!(fen, "{}", empty).unwrap();
+            }
+            if y > 0 {
+                fen.push('/');
+            }
+        }
+        fen.push_str(match self.side() {
+            Side::White => " w ",
+            Side::Black => " b ",
+        });
+
+        let mut rights = String::new();
+        for side in [Side::White, Side::Black] {
+            let (long, short) = self.side_castle_rights(side);
+            let [long_rook, short_rook] = self.home_rooks(side);
+            let rooks = self.side_bitboards(side)[ROOK] >> (side.home_y() * 8) & 0xFF;
+            // X-FEN only names the file if another rook stands further out on the same wing
+            let wings = [
+                (short, short_rook, 'k', rooks >> (short_rook.x() + 1)),
+                (long, long_rook, 'q', rooks & ((1 << long_rook.x()) - 1)),
+            ];
+            for (allowed, rook, letter, further_out) in wings {
+                if !allowed {
+                    continue;
+                }
+                let letter = if shredder || further_out != 0 {
+                    (b'a' + rook.x()) as char
+                } else {
+                    letter
+                };
+                rights.push(match side {
+                    Side::White => letter.to_ascii_uppercase(),
+                    Side::Black => letter,
+                });
+            }
+        }
+        fen.push_str(if rights.is_empty() { "-" } else { &rights });
+
+        match self.en_passant_square {
+            Some(square) => write!(fen, " {}", square).unwrap(),
+            None => fen.push_str(" -"),
+        }
+        write!(fen, " {} {}", self.halfmove_clock, self.fullmove_number).unwrap();
+        fen
     }
 }
 
@@ -324,6 +498,7 @@ impl Default for SearchBoard {
         Self {
             state,
             halfmove_clock: 0,
+            fullmove_number: 1,
         }
     }
 }
@@ -337,6 +512,12 @@ pub struct BoardState {
     pub en_passant_square: Option<Position>,
     pub white_castling: (bool, bool), // long, short
     pub black_castling: (bool, bool), // long, short
+    /// Files of the rooks the castling rights belong to, a and h unless playing Chess960
+    pub white_rook_files: (u8, u8), // long, short
+    pub black_rook_files: (u8, u8),   // long, short
+    /// Castling moves are written as the king taking its own rook, the way Chess960 GUIs
+    /// expect them
+    pub chess960: bool,
     pub zobrist: u64,
     pub white_king: Position,
     pub black_king: Position,
@@ -460,9 +641,14 @@ impl BoardState {
     }
 
     pub fn from_fen(fen: &str) -> Self {
-        let split = fen.split(" ").collect::<Vec<_>>();
-        let [piece_data, active, rights, ep, _, _] = split[..6] else {
-            panic!("Invalid FEN")
+        Self::try_from_fen(fen).expect("Invalid FEN")
+    }
+    /// Parses `fen`, or returns `None` if it doesn't describe a position the engine can play.
+    /// The move counters are optional.
+    pub fn try_from_fen(fen: &str) -> Option<Self> {
+        let split = fen.split_whitespace().collect::<Vec<_>>();
+        let [piece_data, active, rights, ep, ..] = split[..] else {
+            return None;
         };
         let mut white_bits = Bitboards { state: [0; 6] };
         let mut black_bits = Bitboards { state: [0; 6] };
@@ -475,13 +661,20 @@ impl BoardState {
                 match i {
                     '1'..='8' => {
                         x += (i as usize) - (b'0' as usize);
+                        if x > 8 {
+                            return None;
+                        }
                         continue;
                     }
                     '/' => {
+                        if x != 8 || y == 0 {
+                            return None;
+                        }
                         x = 0;
                         y -= 1;
                         continue;
                     }
+                    _ if x >= 8 => return None,
                     'p' => {
                         temp[square] = Some(Piece::black(Pawn));
                         black_bits.state[PAWN] |= 1 << square
@@ -530,41 +723,83 @@ impl BoardState {
                         temp[square] = Some(Piece::white(King));
                         white_bits.state[KING] |= 1 << square
                     }
-                    _ => panic!("Invalid FEN"),
+                    _ => return None,
                 }
                 x += 1;
             }
+            if x != 8 || y != 0 {
+                return None;
+            }
             BoardRepr { board: temp }
         };
+        // move generation relies on both kings being there and pawns never standing on the
+        // first or last rank
+        let back_ranks = 0xFF000000000000FF;
+        if white_bits.state[KING].count_ones() != 1
+            || black_bits.state[KING].count_ones() != 1
+            || (white_bits.state[PAWN] | black_bits.state[PAWN]) & back_ranks != 0
+        {
+            return None;
+        }
 
         let active = match active {
             "w" => Side::White,
             "b" => Side::Black,
-            _ => panic!("Invalid FEN"),
+            _ => return None,
         };
         let mut white_rights = (false, false);
         let mut black_rights = (false, false);
+        let mut white_rook_files = (0, 7);
+        let mut black_rook_files = (0, 7);
 
+        // KQkq stand for the outermost rook on that side of the king (X-FEN), a file letter for
+        // the rook on that file (Shredder-FEN)
         for i in rights.chars() {
-            match i {
-                'K' | 'H' => white_rights.1 = true,
-                'Q' | 'A' => white_rights.0 = true,
-                'k' | 'h' => black_rights.1 = true,
-                'q' | 'a' => black_rights.0 = true,
+            let (side, bits, rights, files) = match i {
                 '-' => break,
-                _ => panic!("Invalid FEN"),
+                'K' | 'Q' | 'A'..='H' => (
+                    Side::White,
+                    &white_bits,
+                    &mut white_rights,
+                    &mut white_rook_files,
+                ),
+                'k' | 'q' | 'a'..='h' => (
+                    Side::Black,
+                    &black_bits,
+                    &mut black_rights,
+                    &mut black_rook_files,
+                ),
+                _ => return None,
+            };
+            let home_rank = bits.state[KING] >> (side.home_y() * 8) & 0xFF;
+            let rooks = bits.state[ROOK] >> (side.home_y() * 8) & 0xFF;
+            // rights the pieces can't back up are dropped, castling would move pieces that
+            // aren't there
+            if home_rank == 0 {
+                continue;
+            }
+            let king_file = home_rank.trailing_zeros() as u8;
+            let rook_file = match i.to_ascii_lowercase() {
+                'k' => (king_file + 1..8).rev().find(|x| rooks & (1 << x) != 0),
+                'q' => (0..king_file).find(|x| rooks & (1 << x) != 0),
+                file => Some(file as u8 - b'a').filter(|x| rooks & (1 << x) != 0),
+            };
+            match rook_file {
+                Some(file) if file < king_file => {
+                    rights.0 = true;
+                    files.0 = file;
+                }
+                Some(file) => {
+                    rights.1 = true;
+                    files.1 = file;
+                }
+                None => {}
             }
         }
+
         let en_passant_square = match ep {
             "-" => None,
-            ep => {
-                let mut chars = ep.chars();
-                let ep_square = Position::new(
-                    (chars.next().expect("Invalid FEN") as u8) - b'a',
-                    (chars.next().expect("Invalid FEN") as u8) - b'1',
-                );
-                Some(ep_square)
-            }
+            ep => Some(Position::from_str(ep)?),
         };
 
         let mut temp_board = BoardState {
@@ -577,10 +812,13 @@ impl BoardState {
             en_passant_square,
             white_castling: white_rights,
             black_castling: black_rights,
+            white_rook_files,
+            black_rook_files,
+            chess960: false,
             zobrist: 0,
         };
         ZOBRIST_RANDOM.hash_board(&mut temp_board);
-        temp_board
+        Some(temp_board)
     }
 
     pub fn side_castle_rights_mut(&mut self, side: Side) -> &mut (bool, bool) {
@@ -595,6 +833,31 @@ impl BoardState {
             Side::Black => self.black_castling,
         }
     }
+    pub fn side_rook_files(&self, side: Side) -> (u8, u8) {
+        match side {
+            Side::White => self.white_rook_files,
+            Side::Black => self.black_rook_files,
+        }
+    }
+    /// Where the castling rooks of `side` start, long side first
+    pub fn home_rooks(&self, side: Side) -> [Position; 2] {
+        let (long, short) = self.side_rook_files(side);
+        [
+            Position::new(long, side.home_y()),
+            Position::new(short, side.home_y()),
+        ]
+    }
+    /// The square of the castling rook, and where the king and that rook end up. Whatever
+    /// they start on, castling long puts them on the c and d files, short on the g and f files.
+    pub fn castling_squares(&self, side: Side, long: bool) -> (Position, Position, Position) {
+        let [long_rook, short_rook] = self.home_rooks(side);
+        let y = side.home_y();
+        if long {
+            (long_rook, Position::new(2, y), Position::new(3, y))
+        } else {
+            (short_rook, Position::new(6, y), Position::new(5, y))
+        }
+    }
 
     pub fn side_bitboards_mut(&mut self, side: Side) -> &mut Bitboards {
         match side {
@@ -646,6 +909,9 @@ impl Default for BoardState {
             side: Side::White,
             black_castling: (true, true),
             white_castling: (true, true),
+            white_rook_files: (0, 7),
+            black_rook_files: (0, 7),
+            chess960: false,
             en_passant_square: None,
             zobrist: 0,
         };
@@ -1,5 +1,5 @@
 use std::{
-    io::{BufWriter, Write, stdout},
+    io::{self, BufWriter, Write, stdout},
     ops::{Add, Index, IndexMut},
 };
 
@@ -152,9 +152,13 @@ impl Index<usize> for BoardRepr {
 }
 
 pub fn print_board(board: &BoardRepr) {
-    let mut stdout = BufWriter::new(stdout());
+    write_board(&mut BufWriter::new(stdout()), board).unwrap();
+}
+
+/// Draws `board` the way `print_board` does, into any writer
+pub fn write_board(out: &mut impl Write, board: &BoardRepr) -> io::Result<()> {
     for row in (0usize..8).rev() {
-        write!(stdout, "{}", row.add(1).fg::<Red>().bg::<Black>()).unwrap();
+        write!(out, "{}", row.add(1).fg::<Red>().bg::<Black>())?;
 
         for col in 0..8 {
             let (fg, bg) = if (row + col) % 2 == 0 {
@@ -164,7 +168,7 @@ pub fn print_board(board: &BoardRepr) {
             };
 
             write!(
-                stdout,
+                out,
                 "{}{}",
                 " ".color(fg).on_color(bg),
                 board[row * 8 + col]
@@ -172,24 +176,22 @@ pub fn print_board(board: &BoardRepr) {
                     .unwrap_or(' ')
                     .color(fg)
                     .on_color(bg)
-            )
-            .unwrap();
+            )?;
         }
-        write!(stdout, "\n").unwrap();
+        write!(out, "\n")?;
     }
-    write!(stdout, "{}", " ".bg::<Black>()).unwrap();
+    write!(out, "{}", " ".bg::<Black>())?;
     for col in 0..8 {
         write!(
-            stdout,
+            out,
             "{}",
             format!(" {}", (b'a' + col) as char)
                 .fg::<Red>()
                 .bg::<Black>()
-        )
-        .unwrap();
+        )?;
     }
 
-    stdout.write(b"\n").unwrap();
+    out.write_all(b"\n")?;
 
-    stdout.flush().unwrap();
+    out.flush()
 }
@@ -0,0 +1,255 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::{engine::search_info::InfoScore, moving::Move};
+
+/// Commands the GUI sends in the XBoard (CECP) protocol
+#[derive(Clone, Debug, PartialEq)]
+pub enum CecpCommand {
+    Xboard,
+    Protover(u32),
+    /// Answers to the `feature` line, one per feature
+    Accepted(String),
+    Rejected(String),
+    New,
+    Force,
+    Go,
+    PlayOther,
+    UserMove(String),
+    /// `?`, play the best move found so far
+    MoveNow,
+    Level {
+        /// 0 means the whole game has to be played in `base`
+        moves_per_control: u32,
+        base: Duration,
+        increment: Duration,
+    },
+    /// `st`, a fixed time for every move
+    MoveTime(Duration),
+    /// `sd`, the deepest the engine may search
+    Depth(u32),
+    /// The engine's clock
+    Time(Duration),
+    /// The opponent's clock
+    Otim(Duration),
+    Undo,
+    Remove,
+    Result {
+        result: String,
+        comment: String,
+    },
+    SetBoard(String),
+    Post,
+    NoPost,
+    Ping(u32),
+    Quit,
+    /// Commands the engine knows, but has no use for (e.g. `random`, `hard`, `computer`)
+    Ignored(String),
+}
+
+/// What the engine sends back to the GUI
+pub enum CecpReply {
+    Feature(Vec<(&'static str, String)>),
+    Move(Move),
+    Pong(u32),
+    /// `post` output after every finished depth
+    Thinking {
+        depth: u32,
+        score: InfoScore,
+        time: Duration,
+        nodes: u64,
+        pv: Vec<Move>,
+    },
+    IllegalMove {
+        mov: String,
+        reason: &'static str,
+    },
+    Error(CecpParseError),
+    /// Claims the end of the game, e.g. `1-0 {White mates}`
+    Result {
+        result: &'static str,
+        comment: &'static str,
+    },
+    TellUserError(String),
+}
+
+/// A line the engine couldn't make sense of, reported as `Error (reason): command`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CecpParseError {
+    pub reason: &'static str,
+    pub command: String,
+}
+
+impl Display for CecpParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error ({}): {}", self.reason, self.command)
+    }
+}
+
+impl std::error::Error for CecpParseError {}
+
+impl CecpCommand {
+    /// Parses one line, `None` if it is empty
+    pub fn parse(line: &str) -> Option<Result<Self, CecpParseError>> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        Some(Self::parse_line(line))
+    }
+
+    fn parse_line(line: &str) -> Result<Self, CecpParseError> {
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        let error = |reason| CecpParseError {
+            reason,
+            command: line.to_string(),
+        };
+        let number = |arg: &str| arg.parse().map_err(|_| error("invalid number"));
+        let command = match command {
+            "xboard" => CecpCommand::Xboard,
+            "protover" => CecpCommand::Protover(number(args)?),
+            "accepted" => CecpCommand::Accepted(args.to_string()),
+            "rejected" => CecpCommand::Rejected(args.to_string()),
+            "new" => CecpCommand::New,
+            "force" => CecpCommand::Force,
+            "go" => CecpCommand::Go,
+            "playother" => CecpCommand::PlayOther,
+            "usermove" if args.is_empty() => return Err(error("missing move")),
+            "usermove" => CecpCommand::UserMove(args.to_string()),
+            "?" => CecpCommand::MoveNow,
+            "level" => {
+                let [moves_per_control, base, increment] =
+                    args.split_whitespace().collect::<Vec<_>>()[..]
+                else {
+                    return Err(error("level needs 3 arguments"));
+                };
+                CecpCommand::Level {
+                    moves_per_control: number(moves_per_control)?,
+                    base: parse_base_time(base).ok_or_else(|| error("invalid base time"))?,
+                    increment: parse_seconds(increment)
+                        .ok_or_else(|| error("invalid increment"))?,
+                }
+            }
+            "st" => {
+                CecpCommand::MoveTime(parse_seconds(args).ok_or_else(|| error("invalid time"))?)
+            }
+            "sd" => CecpCommand::Depth(number(args)?),
+            "time" => {
+                CecpCommand::Time(parse_centiseconds(args).ok_or_else(|| error("invalid time"))?)
+            }
+            "otim" => {
+                CecpCommand::Otim(parse_centiseconds(args).ok_or_else(|| error("invalid time"))?)
+            }
+            "undo" => CecpCommand::Undo,
+            "remove" => CecpCommand::Remove,
+            "result" => {
+                let (result, comment) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                CecpCommand::Result {
+                    result: result.to_string(),
+                    comment: comment.trim().to_string(),
+                }
+            }
+            "setboard" if args.is_empty() => return Err(error("missing FEN")),
+            "setboard" => CecpCommand::SetBoard(args.to_string()),
+            "post" => CecpCommand::Post,
+            "nopost" => CecpCommand::NoPost,
+            "ping" => CecpCommand::Ping(number(args)?),
+            "quit" => CecpCommand::Quit,
+            "random" | "hard" | "easy" | "computer" | "name" | "rating" | "ics" | "white"
+            | "black" | "draw" | "bk" | "hint" | "." => CecpCommand::Ignored(line.to_string()),
+            // without the usermove feature, moves come on their own
+            mov if is_coordinate_move(mov) && args.is_empty() => {
+                CecpCommand::UserMove(mov.to_string())
+            }
+            _ => return Err(error("unknown command")),
+        };
+        Ok(command)
+    }
+
+    /// Whether the command ends the engine's turn, whatever it was thinking about
+    pub fn ends_turn(&self) -> bool {
+        matches!(
+            self,
+            CecpCommand::MoveNow
+                | CecpCommand::New
+                | CecpCommand::Force
+                | CecpCommand::Result { .. }
+                | CecpCommand::Undo
+                | CecpCommand::Remove
+                | CecpCommand::SetBoard(_)
+                | CecpCommand::Quit
+        )
+    }
+}
+
+fn is_coordinate_move(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    matches!(bytes.len(), 4 | 5)
+        && matches!(bytes[0], b'a'..=b'h')
+        && matches!(bytes[1], b'1'..=b'8')
+        && matches!(bytes[2], b'a'..=b'h')
+        && matches!(bytes[3], b'1'..=b'8')
+        && bytes
+            .get(4)
+            .is_none_or(|promotion| b"nbrq".contains(promotion))
+}
+
+// `level` gives the base time in minutes, or as minutes:seconds
+fn parse_base_time(base: &str) -> Option<Duration> {
+    let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    Some(Duration::from_secs(minutes * 60 + seconds))
+}
+
+fn parse_seconds(seconds: &str) -> Option<Duration> {
+    let seconds: f64 = seconds.parse().ok()?;
+    Duration::try_from_secs_f64(seconds.max(0.0)).ok()
+}
+
+// clocks may go negative once the flag fell
+fn parse_centiseconds(centiseconds: &str) -> Option<Duration> {
+    let centiseconds: i64 = centiseconds.parse().ok()?;
+    Some(Duration::from_millis(centiseconds.max(0) as u64 * 10))
+}
+
+impl Display for CecpReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CecpReply::Feature(features) => {
+                write!(f, "feature")?;
+                for (name, value) in features {
+                    write!(f, " {}={}", name, value)?;
+                }
+                Ok(())
+            }
+            CecpReply::Move(mov) => write!(f, "move {}", mov.into_long_algebraic()),
+            CecpReply::Pong(number) => write!(f, "pong {}", number),
+            CecpReply::Thinking {
+                depth,
+                score,
+                time,
+                nodes,
+                pv,
+            } => {
+                // mates are sent as 100000 + moves to mate
+                let score = match score {
+                    InfoScore::Centipawns(centipawns) => *centipawns,
+                    InfoScore::Mate(moves) if *moves >= 0 => 100000 + moves,
+                    InfoScore::Mate(moves) => -100000 + moves,
+                };
+                write!(f, "{} {} {} {}", depth, score, time.as_millis() / 10, nodes)?;
+                for mov in pv {
+                    write!(f, " {}", mov.into_long_algebraic())?;
+                }
+                Ok(())
+            }
+            CecpReply::IllegalMove { mov, reason } => {
+                write!(f, "Illegal move ({}): {}", reason, mov)
+            }
+            CecpReply::Error(error) => write!(f, "{}", error),
+            CecpReply::Result { result, comment } => write!(f, "{} {{{}}}", result, comment),
+            CecpReply::TellUserError(message) => write!(f, "tellusererror {}", message),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    collections::VecDeque,
+    io::{Stdout, Write},
+    time::Duration,
+};
+
+use crate::{
+    cecp::{
+        command::{CecpCommand, CecpReply},
+        stream::CecpStream,
+    },
+    engine::{Bot, evaluate::Outcome},
+    moving::Move,
+    piece::Side,
+    uci::{
+        command::GoParams,
+        session::{ENGINE_NAME, search_limits},
+    },
+};
+
+/// Time control set by `level`, `st` and `sd`, plus the clocks from `time` and `otim`
+#[derive(Clone, Debug, Default)]
+struct Clock {
+    moves_per_control: u32,
+    base: Duration,
+    increment: Duration,
+    move_time: Option<Duration>,
+    depth: Option<u32>,
+    engine_time: Option<Duration>,
+    opponent_time: Option<Duration>,
+}
+
+impl Clock {
+    /// The same limits as `go` parameters, so both protocols pick the search depth alike
+    fn go_params(&self, engine_side: Side, plies_played: usize) -> GoParams {
+        let base = (!self.base.is_zero()).then_some(self.base);
+        let engine_time = self.engine_time.or(base);
+        let opponent_time = self.opponent_time.or(base);
+        let (wtime, btime) = match engine_side {
+            Side::White => (engine_time, opponent_time),
+            Side::Black => (opponent_time, engine_time),
+        };
+        let increment = (!self.increment.is_zero()).then_some(self.increment);
+        let moves_played = (plies_played / 2) as u32;
+        let movestogo = (self.moves_per_control > 0)
+            .then(|| self.moves_per_control - moves_played % self.moves_per_control);
+        GoParams {
+            wtime,
+            btime,
+            winc: increment,
+            binc: increment,
+            movestogo,
+            movetime: self.move_time,
+            depth: self.depth,
+            ..Default::default()
+        }
+    }
+}
+
+/// Drives the same `Bot` as `UciSession`, through the XBoard protocol
+pub struct CecpSession<W: Write = Stdout> {
+    stream: CecpStream<W>,
+    game: Bot,
+    /// `undo` replays the game from here, `None` is the starting position
+    start_fen: Option<String>,
+    moves: Vec<Move>,
+    /// `None` in force mode
+    engine_side: Option<Side>,
+    clock: Clock,
+    post: bool,
+    // commands that arrived while the engine was thinking
+    pending: VecDeque<CecpCommand>,
+}
+
+impl<W: Write + Send + 'static> CecpSession<W> {
+    pub fn new(stream: CecpStream<W>) -> Self {
+        Self {
+            stream,
+            game: Bot::default(),
+            start_fen: None,
+            moves: Vec::new(),
+            engine_side: Some(Side::Black),
+            clock: Clock::default(),
+            post: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn into_stream(self) -> CecpStream<W> {
+        self.stream
+    }
+
+    /// Handles commands until the GUI sends `quit` or closes the stream
+    pub fn run(&mut self) {
+        while let Some(command) = self.pending.pop_front().or_else(|| self.stream.read()) {
+            match command {
+                CecpCommand::Protover(_) => self.stream.send(CecpReply::Feature(vec![
+                    ("ping", "1".to_string()),
+                    ("setboard", "1".to_string()),
+                    ("playother", "1".to_string()),
+                    ("usermove", "1".to_string()),
+                    ("time", "1".to_string()),
+                    ("san", "0".to_string()),
+                    ("colors", "0".to_string()),
+                    ("draw", "0".to_string()),
+                    ("analyze", "0".to_string()),
+                    ("sigint", "0".to_string()),
+                    ("sigterm", "0".to_string()),
+                    ("reuse", "1".to_string()),
+                    ("myname", format!("\"{}\"", ENGINE_NAME)),
+                    ("done", "1".to_string()),
+                ])),
+                CecpCommand::New => {
+                    self.game.clear_hash();
+                    self.set_board(None);
+                    self.engine_side = Some(Side::Black);
+                    // `new` keeps the time control, but not the clocks or the depth limit
+                    self.clock.depth = None;
+                    self.clock.engine_time = None;
+                    self.clock.opponent_time = None;
+                }
+                CecpCommand::Force | CecpCommand::Result { .. } => self.engine_side = None,
+                CecpCommand::Go => {
+                    self.engine_side = Some(self.game.get_board().side());
+                    self.think();
+                }
+                CecpCommand::PlayOther => {
+                    self.engine_side = Some(self.game.get_board().side().opposite())
+                }
+                CecpCommand::UserMove(mov) => self.user_move(&mov),
+                CecpCommand::Level {
+                    moves_per_control,
+                    base,
+                    increment,
+                } => {
+                    self.clock.moves_per_control = moves_per_control;
+                    self.clock.base = base;
+                    self.clock.increment = increment;
+                    self.clock.move_time = None;
+                }
+                CecpCommand::MoveTime(move_time) => self.clock.move_time = Some(move_time),
+                CecpCommand::Depth(depth) => self.clock.depth = Some(depth),
+                CecpCommand::Time(time) => self.clock.engine_time = Some(time),
+                CecpCommand::Otim(time) => self.clock.opponent_time = Some(time),
+                CecpCommand::Undo => self.take_back(1),
+                CecpCommand::Remove => self.take_back(2),
+                CecpCommand::SetBoard(fen) => self.set_board(Some(fen)),
+                CecpCommand::Post => self.post = true,
+                CecpCommand::NoPost => self.post = false,
+                CecpCommand::Ping(number) => self.stream.send(CecpReply::Pong(number)),
+                CecpCommand::Quit => break,
+                // `?` only means something while thinking
+                CecpCommand::Xboard
+                | CecpCommand::Accepted(_)
+                | CecpCommand::Rejected(_)
+                | CecpCommand::MoveNow
+                | CecpCommand::Ignored(_) => {}
+            }
+        }
+        self.stream.shutdown();
+    }
+
+    fn set_board(&mut self, fen: Option<String>) {
+        match &fen {
+            Some(fen) if self.game.load_fen(fen).is_none() => {
+                self.stream
+                    .send(CecpReply::TellUserError("Illegal position".to_string()));
+                return;
+            }
+            Some(_) => {}
+            None => self.game.load_startpos(),
+        }
+        self.start_fen = fen;
+        self.moves.clear();
+    }
+
+    fn take_back(&mut self, plies: usize) {
+        let plies = plies.min(self.moves.len());
+        self.moves.truncate(self.moves.len() - plies);
+        match &self.start_fen {
+            Some(fen) => self.game.load_fen(fen).expect("the FEN was loaded before"),
+            None => self.game.load_startpos(),
+        }
+        for mov in &self.moves {
+            self.game.make_move(mov);
+        }
+    }
+
+    fn user_move(&mut self, mov: &str) {
+        let Some(parsed) = self.game.parse_move(mov) else {
+            self.stream.send(CecpReply::IllegalMove {
+                mov: mov.to_string(),
+                reason: "illegal",
+            });
+            return;
+        };
+        self.play(parsed);
+        if self.engine_side == Some(self.game.get_board().side()) {
+            self.think();
+        }
+    }
+
+    fn play(&mut self, mov: Move) {
+        self.game.make_move(&mov);
+        self.moves.push(mov);
+
+        let (result, comment) = match self.game.outcome() {
+            Outcome::Ongoing => return,
+            Outcome::WhiteWon => ("1-0", "White mates"),
+            Outcome::BlackWon => ("0-1", "Black mates"),
+            Outcome::Stalemate => ("1/2-1/2", "Draw"),
+        };
+        self.stream.send(CecpReply::Result { result, comment });
+    }
+
+    /// Searches the current position and plays the best move, unless the GUI took the turn away
+    fn think(&mut self) {
+        if self.game.outcome().is_game_over() {
+            return;
+        }
+        let stop = self.stream.stop_token();
+        stop.reset(false);
+        // an interrupt queued before the reset still has to stop this search
+        while let Some(command) = self.stream.try_read() {
+            if command.ends_turn() {
+                stop.stop();
+            }
+            self.pending.push_back(command);
+        }
+
+        let stream = &self.stream;
+        let game = &mut self.game;
+        let post = self.post;
+        game.set_stop_token(stop.clone());
+        let side = game.get_board().side();
+        let params = self.clock.go_params(side, self.moves.len());
+        let best_move = game
+            .find_best_moves_reporting(search_limits(&params, game), |info| {
+                if let (true, Some(depth), Some(score)) = (post, info.depth, info.score) {
+                    stream.send(CecpReply::Thinking {
+                        depth,
+                        score,
+                        time: info.time.unwrap_or_default(),
+                        nodes: info.nodes.unwrap_or_default(),
+                        pv: info.pv,
+                    });
+                }
+            })
+            .map(|result| result.best_move);
+
+        if stop.is_stopped() {
+            // whatever stopped the search is queued by now. Only `?` still wants the move.
+            while let Some(command) = self.stream.try_read() {
+                self.pending.push_back(command);
+            }
+            let abandoned = self
+                .pending
+                .iter()
+                .any(|command| command.ends_turn() && *command != CecpCommand::MoveNow);
+            if abandoned {
+                return;
+            }
+        }
+        let Some(best_move) = best_move else {
+            return;
+        };
+        self.stream.send(CecpReply::Move(best_move));
+        self.play(best_move);
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{self, Stdout, Write};
+
+use crate::{
+    cecp::command::{CecpCommand, CecpParseError, CecpReply},
+    line_stream::{Interrupt, LineStream, Protocol},
+};
+
+/// Both ends of an XBoard conversation. Lines that don't parse are answered with an `Error`
+/// line.
+pub type CecpStream<W = Stdout> = LineStream<Cecp, W>;
+
+#[derive(Default)]
+pub struct Cecp;
+
+impl Protocol for Cecp {
+    type Command = CecpCommand;
+    type ParseError = CecpParseError;
+    type Reply = CecpReply;
+
+    fn parse(line: &str) -> Option<Result<CecpCommand, CecpParseError>> {
+        CecpCommand::parse(line)
+    }
+
+    fn error_reply(err: CecpParseError) -> CecpReply {
+        CecpReply::Error(err)
+    }
+
+    fn write_reply(writer: &mut impl Write, reply: &CecpReply) -> io::Result<()> {
+        writeln!(writer, "{}", reply)
+    }
+
+    fn is_quit(command: &CecpCommand) -> bool {
+        matches!(command, CecpCommand::Quit)
+    }
+
+    /// Every command that ends the engine's turn stops the search
+    fn interrupt(&mut self, command: &CecpCommand) -> Interrupt {
+        if command.ends_turn() {
+            Interrupt::Stop
+        } else {
+            Interrupt::None
+        }
+    }
+
+    /// Every search is bounded, so like in UCI a running one may finish first
+    fn end_of_input(&mut self) -> Interrupt {
+        Interrupt::None
+    }
+
+    /// `ping` is answered once the move is made, so everything queued waits for the search
+    fn busy_reply(_: &CecpCommand) -> Option<CecpReply> {
+        None
+    }
+}
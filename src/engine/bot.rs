@@ -1,9 +1,13 @@
-use owo_colors::{OwoColorize, colors::Green};
-use rayon::prelude::*;
+use arrayvec::ArrayVec;
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
-    time::{Duration, SystemTime},
+    mem,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Instant, SystemTime},
 };
 
 use nohash_hasher::BuildNoHashHasher;
@@ -13,56 +17,139 @@ use crate::{
     board_repr::print_board,
     engine::{
         RepetitionHashmap, add_board_to_repetition,
-        evaluate::{Outcome, evaluate, outcome, rate_move},
-        searcher::SearchContext,
+        config::SearchConfig,
+        evaluate::{EvalBreakdown, Outcome, centipawns_to_eval, evaluate, outcome},
+        move_ordering::MoveOrdering,
+        score::Score,
+        search_info::{InfoScore, PvLine, SearchInfo, SearchResult, nodes_per_second},
+        search_limits::SearchLimits,
+        searcher::{MAX_DEPTH, SearchContext},
+        stop_token::StopToken,
+        time_manager::TimeManager,
         transposition_table::TranspositionTable,
     },
-    moving::Move,
+    moving::{Move, Unmove},
 };
 
+/// Half the width of the first aspiration window, in centipawns. Every time the score falls
+/// outside, the window is widened by twice as much on that side.
+const ASPIRATION_WINDOW: i64 = 25;
+
+/// A searched root move, with the line the search expects after it
+#[derive(Clone)]
+struct RootMove {
+    mov: Move,
+    eval: Score,
+    /// starts with `mov`
+    pv: Vec<Move>,
+}
+
+/// What the searches of the root moves add up to, over all iterations
+#[derive(Default)]
+struct RootStats {
+    seldepth: i32,
+    timed_out: bool,
+}
+
 pub struct Bot {
     board: SearchBoard,
     repetitions: RepetitionHashmap,
     last_move_outcome: Outcome,
+    stop: StopToken,
+    config: SearchConfig,
+    // kept between searches, so the next move can start from what the last one found
+    ttable: Arc<TranspositionTable>,
+    ordering: MoveOrdering,
+    /// nodes searched by every thread of the running search
+    nodes: Arc<AtomicU64>,
 }
 
 impl Bot {
     pub fn from_fen(fen: &str) -> Self {
-        let board = SearchBoard::from_fen(fen);
-        let repetitions = HashMap::with_hasher(BuildNoHashHasher::new());
+        let mut bot = Self::default();
+        bot.load_fen(fen).expect("Invalid FEN");
+        bot
+    }
+    /// Sets up the position in `fen`, keeping the configuration and the transposition table.
+    /// Returns `None` and leaves the current position alone if `fen` is invalid.
+    pub fn load_fen(&mut self, fen: &str) -> Option<()> {
+        self.board = SearchBoard::try_from_fen(fen)?;
+        self.board.chess960 = self.config.chess960;
+        self.repetitions.clear();
 
-        let (pin_state, check_paths) = board.legal_data();
+        let (pin_state, check_paths) = self.board.legal_data();
         let is_check = check_paths.is_check();
-        let moves = board.find_all_moves(pin_state, check_paths, false);
+        let moves = self.board.find_all_moves(pin_state, check_paths, false);
 
-        let outcome = outcome(&board, !moves.is_empty(), is_check, &repetitions);
-        Self {
-            board,
-            repetitions,
-            last_move_outcome: outcome,
+        self.last_move_outcome =
+            outcome(&self.board, !moves.is_empty(), is_check, &self.repetitions);
+        Some(())
+    }
+    /// Sets up the starting position, keeping the configuration and the transposition table
+    pub fn load_startpos(&mut self) {
+        self.board = SearchBoard::default();
+        self.board.chess960 = self.config.chess960;
+        self.repetitions.clear();
+        self.last_move_outcome = Outcome::Ongoing;
+    }
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: SearchConfig) {
+        if config.hash_size_mb != self.config.hash_size_mb {
+            self.ttable = Arc::new(TranspositionTable::new(config.hash_size_mb));
         }
+        // only changes how castling moves are written, the position stays the same
+        self.board.chess960 = config.chess960;
+        self.config = config;
+    }
+    /// Forgets everything learned in earlier searches, the hash table and the move ordering
+    pub fn clear_hash(&mut self) {
+        self.ttable.clear();
+        self.ordering = MoveOrdering::new();
     }
     pub fn get_board(&self) -> &SearchBoard {
         &self.board
     }
-    pub fn static_evaluate(&self) -> i64 {
-        return evaluate(&self.board, &self.repetitions, 0);
+    pub fn legal_moves(&self) -> ArrayVec<Move, 219> {
+        let (pin_state, check_paths) = self.board.legal_data();
+        self.board.find_all_moves(pin_state, check_paths, false)
+    }
+    /// Finds the legal move written in long algebraic notation (e.g. `e2e4`, `e7e8q`). With
+    /// `chess960` set, castling is written as the king taking its own rook (e.g. `e1h1`).
+    pub fn parse_move(&self, long_algebraic: &str) -> Option<Move> {
+        self.legal_moves()
+            .into_iter()
+            .find(|mov| mov.into_long_algebraic() == long_algebraic)
+    }
+    pub fn static_evaluate(&self) -> Score {
+        return evaluate(&self.board, &self.repetitions, 0, -self.contempt());
+    }
+    pub fn eval_breakdown(&self) -> EvalBreakdown {
+        EvalBreakdown::new(&self.board, &self.repetitions, 0, -self.contempt())
     }
-    pub fn make_best_move(&mut self, depth: i32) -> Outcome {
+    /// How much worse than equal a draw is for the side to move, from the config
+    fn contempt(&self) -> Score {
+        Score::new(centipawns_to_eval(self.config.contempt))
+    }
+    pub fn make_best_move(&mut self, limits: SearchLimits) -> Outcome {
         if self.last_move_outcome.is_game_over() {
             return self.last_move_outcome;
         }
-        let Some((mov, _)) = self.find_best_move(depth) else {
-            let (_, check_paths) = self.board.legal_data();
-            self.last_move_outcome = outcome(
-                &self.board,
-                false,
-                check_paths.is_check(),
-                &self.repetitions,
-            );
+        let Some(result) = self.find_best_moves(limits) else {
+            // the limits may have left nothing to search in a game that goes on
+            if self.legal_moves().is_empty() {
+                let (_, check_paths) = self.board.legal_data();
+                self.last_move_outcome = outcome(
+                    &self.board,
+                    false,
+                    check_paths.is_check(),
+                    &self.repetitions,
+                );
+            }
             return self.last_move_outcome;
         };
-        self.make_move(&mov);
+        self.make_move(&result.best_move);
 
         return self.last_move_outcome;
     }
@@ -89,68 +176,378 @@ impl Bot {
         }
     }
 
-    pub fn find_best_move(&mut self, depth: i32) -> Option<(Move, i64)> {
-        self.find_best_moves(depth)?.get(0).map(|e| *e)
+    pub fn find_best_move(&mut self, limits: SearchLimits) -> Option<(Move, Score)> {
+        self.find_best_moves(limits)
+            .map(|result| (result.best_move, result.score))
+    }
+
+    /// Searches until one of the `limits` is hit. Returns `None` if the game is already over
+    /// or the limits leave no root move to search.
+    pub fn find_best_moves(&mut self, limits: SearchLimits) -> Option<SearchResult> {
+        self.find_best_moves_reporting(limits, |_| {})
     }
 
-    pub fn find_best_moves(&mut self, depth: i32) -> Option<Vec<(Move, i64)>> {
+    /// Same as `find_best_moves`, but reports the progress of the search through `report`.
+    /// Deepens one ply at a time, every iteration searching the root moves in the order of the
+    /// last one's scores, within an aspiration window around the last best score. If the stop
+    /// token fires, the time runs out or the node limit is reached, the result of the last
+    /// completed depth is returned. No new depth is started after the soft limit of the clock,
+    /// and with a single legal move, depth 1 is all a timed search looks at. While the token is
+    /// pondering, the search keeps deepening past the depth limit.
+    ///
+    /// Lazy SMP: with more than one thread configured, helper threads search the same position
+    /// over and over, filling the shared transposition table for this thread, which is the one
+    /// that reports and decides. With one thread everything runs on the calling thread, and the
+    /// same search from the same state always gives the same result.
+    pub fn find_best_moves_reporting(
+        &mut self,
+        mut limits: SearchLimits,
+        report: impl FnMut(SearchInfo),
+    ) -> Option<SearchResult> {
         if self.last_move_outcome.is_game_over() {
             return None;
         }
+        self.ordering.age();
+        self.ttable.new_search();
+        self.nodes.store(0, Ordering::Relaxed);
 
-        let (pin_state, check_paths) = self.board.legal_data();
-        let mut moves = self.board.find_all_moves(pin_state, check_paths, false);
-        moves.sort_by_cached_key(|e| -rate_move(e, self.board.side()));
+        if let Some(move_time) = limits.move_time.filter(|_| limits.time.is_none()) {
+            limits.time = Some(TimeManager::fixed(move_time, self.config.move_overhead));
+        }
+        if limits.infinite {
+            limits.time = None;
+        }
+        let helpers = self.config.threads.max(1) - 1;
+        if helpers == 0 {
+            return self.iterative_deepening(limits, report, 0);
+        }
+        let stop_helpers = StopToken::new();
+        thread::scope(|scope| {
+            for index in 1..=helpers {
+                let mut helper = self.helper(stop_helpers.clone());
+                // the helpers keep going until the main thread is done, on the same root moves
+                let limits = SearchLimits {
+                    search_moves: limits.search_moves.clone(),
+                    excluded_moves: limits.excluded_moves.clone(),
+                    ..SearchLimits::infinite()
+                };
+                scope.spawn(move || helper.iterative_deepening(limits, |_| {}, index));
+            }
+            let result = self.iterative_deepening(limits, report, 0);
+            stop_helpers.stop();
+            result
+        })
+    }
+
+    /// A copy of this bot for a helper thread, sharing the transposition table and the node
+    /// count, stopped by `stop`
+    fn helper(&self, stop: StopToken) -> Self {
+        Self {
+            board: self.board.clone(),
+            repetitions: self.repetitions.clone(),
+            last_move_outcome: self.last_move_outcome,
+            stop,
+            // the helpers only fill the transposition table, the best move is all they need
+            config: SearchConfig {
+                multi_pv: 1,
+                ..self.config.clone()
+            },
+            ttable: Arc::clone(&self.ttable),
+            ordering: self.ordering.clone(),
+            nodes: Arc::clone(&self.nodes),
+        }
+    }
 
-        let total_nodes = Mutex::new(0);
-        let transposition_table = Arc::new(Mutex::new(TranspositionTable::new()));
+    /// The search of a single thread, `thread` is 0 for the main one. Helpers start at
+    /// staggered depths and with the root moves rotated, so they don't all search the same
+    /// tree in the same order.
+    fn iterative_deepening(
+        &mut self,
+        mut limits: SearchLimits,
+        mut report: impl FnMut(SearchInfo),
+        thread: usize,
+    ) -> Option<SearchResult> {
+        let start = Instant::now();
+        let depth = limits.max_depth();
 
-        let mut evals = moves
+        let (pin_state, check_paths) = self.board.legal_data();
+        let mut moves = self
+            .board
+            .find_all_moves(pin_state, check_paths, false)
             .into_iter()
-            .map(|e| {
-                let mut ctx = SearchContext::new(
-                    self.board.clone(),
-                    self.repetitions.clone(),
-                    e,
-                    Arc::clone(&transposition_table),
-                );
-                let eval = ctx.evaluate(depth, depth);
-                *total_nodes.lock().unwrap() += ctx.nodes_searched;
-                eval
-            })
+            .filter(|mov| limits.allows(mov))
             .collect::<Vec<_>>();
-        evals.sort_by_key(|(_, eval)| -eval);
-        for (mov, eval) in &evals {
-            println!("{eval}: {mov}");
+        if moves.is_empty() {
+            return None;
+        }
+        moves.sort_by_cached_key(|mov| -self.ordering.score(&self.board, mov, 0, None, None));
+        let rotation = thread % moves.len().max(1);
+        moves.rotate_left(rotation);
+        let lines = self.lines(&moves);
+
+        let mut stats = RootStats::default();
+        // every move of the last completed iteration, best first, and its depth
+        let mut completed: Option<(Vec<RootMove>, i32)> = None;
+
+        for current_depth in 1 + (thread % 2) as i32..=MAX_DEPTH {
+            // a ponderhit turns the search back into a depth limited one
+            if current_depth > depth && !self.stop.is_pondering() {
+                break;
+            }
+            let mut delta = centipawns_to_eval(ASPIRATION_WINDOW);
+            // the first iteration has no scores to center the window on, the window reaches from
+            // the last of the best lines to the best one
+            let (mut alpha, mut beta) = match &completed {
+                Some((searched, _)) => (
+                    (searched[lines.min(searched.len()) - 1].eval - delta).max(-Score::INFINITE),
+                    (searched[0].eval + delta).min(Score::INFINITE),
+                ),
+                None => (-Score::INFINITE, Score::INFINITE),
+            };
+            let searched = loop {
+                let searched = self.search_root(
+                    &moves,
+                    current_depth,
+                    (alpha, beta),
+                    &mut limits,
+                    &mut stats,
+                    &mut report,
+                );
+                if self.stop.is_stopped() || stats.timed_out {
+                    // nothing finished yet, the fully searched moves are better than nothing
+                    if completed.is_none() && !searched.is_empty() {
+                        let mut searched = searched;
+                        searched.sort_by_key(|root| -root.eval);
+                        completed = Some((searched, 0));
+                    }
+                    return Self::best_of(completed, &moves, lines, self.nodes());
+                }
+                // the next attempt starts with the moves that did best in this one
+                let mut ordered = searched;
+                ordered.sort_by_key(|root| -root.eval);
+                let unsearched = moves.split_off(ordered.len());
+                moves = ordered
+                    .iter()
+                    .map(|root| root.mov)
+                    .chain(unsearched)
+                    .collect();
+
+                let best_eval = ordered[0].eval;
+                let last_line = ordered[lines.min(ordered.len()) - 1].eval;
+                if last_line <= alpha && alpha > -Score::INFINITE {
+                    alpha = (alpha - delta).max(-Score::INFINITE);
+                } else if best_eval >= beta && beta < Score::INFINITE {
+                    beta = (beta + delta).min(Score::INFINITE);
+                } else {
+                    break ordered;
+                }
+                delta = delta.saturating_mul(2);
+            };
+
+            let best_move = searched[0].mov;
+            let best_eval = searched[0].eval;
+
+            let elapsed = start.elapsed();
+            for (rank, line) in searched.iter().take(lines).enumerate() {
+                report(SearchInfo {
+                    depth: Some(current_depth as u32),
+                    seldepth: Some(stats.seldepth as u32),
+                    multipv: (lines > 1).then_some(rank as u32 + 1),
+                    score: Some(InfoScore::from(line.eval)),
+                    nodes: Some(self.nodes()),
+                    nps: Some(nodes_per_second(self.nodes(), elapsed)),
+                    time: Some(elapsed),
+                    hashfull: Some(self.ttable.hashfull()),
+                    pv: line.pv.clone(),
+                    ..Default::default()
+                });
+            }
+            completed = Some((searched, current_depth));
+
+            if limits.nodes_exhausted(self.nodes()) || limits.mate_found(best_eval) {
+                break;
+            }
+            if let Some(time) = &mut limits.time {
+                // the time spent pondering isn't taken from the clock
+                if self.stop.is_pondering() {
+                    time.restart();
+                    continue;
+                }
+                time.iteration_done(best_move, best_eval);
+                if time.should_stop() || moves.len() == 1 {
+                    break;
+                }
+            }
         }
-        println!("nodes: {}", total_nodes.lock().unwrap().fg::<Green>());
-        println!(
-            "transpositions: {}",
-            transposition_table.lock().unwrap().len().fg::<Green>()
-        );
-        let best_eval = evals.get(0)?.1;
-        evals.retain(|e| best_eval == e.1);
 
-        Some(evals)
+        Self::best_of(completed, &moves, lines, self.nodes())
     }
 
-    pub fn autoplay(&mut self, depth: i32) -> Outcome {
+    /// How many of the root `moves` get an exact score
+    fn lines(&self, moves: &[Move]) -> usize {
+        self.config.multi_pv.clamp(1, moves.len().max(1))
+    }
+
+    fn nodes(&self) -> u64 {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    /// Searches the root moves in order within the window, until one fails high or the `limits`
+    /// run out. The window of every move starts just below the `lines`-th best score so far, so
+    /// moves that may still be among the best lines get their exact score instead of being cut
+    /// off. Returns the searched moves in search order.
+    fn search_root(
+        &mut self,
+        moves: &[Move],
+        depth: i32,
+        (alpha, beta): (Score, Score),
+        limits: &mut SearchLimits,
+        stats: &mut RootStats,
+        report: &mut impl FnMut(SearchInfo),
+    ) -> Vec<RootMove> {
+        let lines = self.lines(moves);
+        let mut searched = Vec::with_capacity(moves.len());
+        // the best `lines` scores so far, best first
+        let mut best_evals = Vec::with_capacity(lines + 1);
+        for (index, mov) in moves.iter().copied().enumerate() {
+            report(SearchInfo {
+                currmove: Some(mov),
+                currmovenumber: Some(index as u32 + 1),
+                ..Default::default()
+            });
+            let mut ctx = SearchContext::new(
+                self.board.clone(),
+                self.repetitions.clone(),
+                mov,
+                Arc::clone(&self.ttable),
+                self.stop.clone(),
+            );
+            ctx.quiescence_checks = self.config.quiescence_checks;
+            ctx.ordering = mem::take(&mut self.ordering);
+            ctx.contempt = self.contempt();
+            ctx.pruning = self.config.pruning;
+            // the first move of depth 1 is always searched to the end, so even a clock that ran
+            // out leaves a searched move to play. Only the stop token cuts it short.
+            let bounded = depth > 1 || index > 0;
+            if let Some(limit) = limits.nodes.filter(|_| bounded && !limits.infinite) {
+                ctx.node_limit = Some(limit.saturating_sub(self.nodes()));
+            }
+            if let Some(time) = &mut limits.time {
+                // until the ponderhit, the clock isn't running
+                if self.stop.is_pondering() {
+                    time.restart();
+                }
+                ctx.deadline = bounded.then(|| time.hard_deadline());
+            }
+            let floor = match best_evals.len() {
+                full if full == lines => best_evals[lines - 1] - 1,
+                _ => -Score::INFINITE,
+            };
+            let (_, eval) = ctx.evaluate(depth, alpha.max(floor), beta);
+            self.ordering = mem::take(&mut ctx.ordering);
+            self.nodes.fetch_add(ctx.nodes_searched, Ordering::Relaxed);
+            stats.seldepth = stats.seldepth.max(ctx.seldepth);
+            if self.stop.is_stopped() || ctx.timed_out {
+                stats.timed_out |= ctx.timed_out;
+                break;
+            }
+            searched.push(RootMove {
+                mov,
+                eval,
+                pv: ctx.pv(),
+            });
+            best_evals.insert(best_evals.partition_point(|best| *best >= eval), eval);
+            best_evals.truncate(lines);
+            if eval >= beta {
+                break;
+            }
+        }
+        searched
+    }
+
+    /// The result of the last completed iteration with its best `lines`, any legal move if the
+    /// search was stopped before a single move was searched
+    fn best_of(
+        completed: Option<(Vec<RootMove>, i32)>,
+        moves: &[Move],
+        lines: usize,
+        nodes: u64,
+    ) -> Option<SearchResult> {
+        let (searched, depth) = completed.unwrap_or_else(|| {
+            let any = moves.first().map(|mov| RootMove {
+                mov: *mov,
+                eval: Score::ZERO,
+                pv: vec![*mov],
+            });
+            (any.into_iter().collect(), 0)
+        });
+        let best = searched.first()?;
+        Some(SearchResult {
+            best_move: best.mov,
+            score: best.eval,
+            pv: best.pv.clone(),
+            depth,
+            nodes,
+            best_moves: searched
+                .iter()
+                .take_while(|root| root.eval == best.eval)
+                .map(|root| root.mov)
+                .collect(),
+            lines: searched
+                .iter()
+                .take(lines)
+                .map(|root| PvLine {
+                    score: root.eval,
+                    pv: root.pv.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Counts the leaf nodes `depth` plies deep below each legal move, like Stockfish's
+    /// `go perft` does
+    pub fn perft_divide(&self, depth: u32) -> Vec<(Move, u64)> {
+        let mut board = self.board.clone();
+        self.legal_moves()
+            .into_iter()
+            .map(|mov| {
+                let unmove = Unmove::new(mov, &board);
+                board.make(&mov);
+                let nodes = perft(&mut board, depth.saturating_sub(1));
+                board.unmake(unmove);
+                (mov, nodes)
+            })
+            .collect()
+    }
+
+    /// Token that aborts a running search of this bot when stopped
+    pub fn stop_token(&self) -> StopToken {
+        self.stop.clone()
+    }
+
+    pub fn set_stop_token(&mut self, stop: StopToken) {
+        self.stop = stop;
+    }
+
+    /// Plays against itself until the game is over, every move searched within `limits`. A
+    /// clock and root move filters only fit a single position, so they are left out.
+    pub fn autoplay(&mut self, limits: SearchLimits) -> Outcome {
+        let limits = SearchLimits {
+            time: None,
+            search_moves: Vec::new(),
+            excluded_moves: Vec::new(),
+            ..limits
+        };
         while !self.last_move_outcome.is_game_over() {
             print_board(&self.board.board);
             let start = SystemTime::now();
-            let (mov, rating) = self.find_best_move(depth).unwrap();
+            let (mov, rating) = self.find_best_move(limits.clone()).unwrap();
             let move_duration = start.elapsed().unwrap();
             self.make_move(&mov);
             println!(
                 "made move {mov} in {} milliseconds (rating: {rating})",
                 move_duration.as_millis()
             );
-
-            std::thread::sleep(
-                Duration::from_secs(1)
-                    .checked_sub(move_duration)
-                    .unwrap_or(Duration::from_secs(0)),
-            );
         }
 
         print_board(&self.board.board);
@@ -163,12 +560,37 @@ impl Bot {
     }
 }
 
+fn perft(board: &mut SearchBoard, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let (pin_state, check_paths) = board.legal_data();
+    let moves = board.find_all_moves(pin_state, check_paths, false);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for mov in moves {
+        let unmove = Unmove::new(mov, board);
+        board.make(&mov);
+        nodes += perft(board, depth - 1);
+        board.unmake(unmove);
+    }
+    nodes
+}
+
 impl Default for Bot {
     fn default() -> Self {
+        let config = SearchConfig::default();
         Self {
             board: SearchBoard::default(),
             repetitions: HashMap::with_hasher(BuildNoHashHasher::new()),
             last_move_outcome: Outcome::Ongoing,
+            stop: StopToken::new(),
+            ttable: Arc::new(TranspositionTable::new(config.hash_size_mb)),
+            ordering: MoveOrdering::new(),
+            nodes: Arc::new(AtomicU64::new(0)),
+            config,
         }
     }
 }
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+/// Tunables of the search, most of them exposed as UCI options
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchConfig {
+    pub hash_size_mb: usize,
+    /// threads searching at once, with 1 the search runs on the calling thread and is
+    /// deterministic
+    pub threads: usize,
+    /// number of best lines to report
+    pub multi_pv: usize,
+    /// time kept in reserve for communication lag on every move
+    pub move_overhead: Duration,
+    /// also search the quiet moves giving check at the first ply of quiescence
+    pub quiescence_checks: bool,
+    /// in centipawns, how much worse than equal a draw is for the engine
+    pub contempt: i64,
+    /// castling moves are written as the king taking its own rook
+    pub chess960: bool,
+    pub pruning: Pruning,
+}
+
+/// The selective parts of the search, each can be turned off to measure what it's worth
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pruning {
+    /// skip the turn, if even that fails high the node isn't worth searching. Verified in
+    /// endgames with little material, where zugzwang makes passing better than any move.
+    pub null_move: bool,
+    /// search quiet moves late in the move order less deep, unless they surprise
+    pub late_move_reductions: bool,
+    /// fail high right away when the static evaluation is far above beta
+    pub reverse_futility: bool,
+    /// skip quiet moves near the horizon when the static evaluation is far below alpha
+    pub futility: bool,
+    /// drop into quiescence near the horizon when the static evaluation is far below alpha
+    pub razoring: bool,
+    /// skip the last quiet moves near the horizon
+    pub late_move_pruning: bool,
+    /// skip quiet moves near the horizon and captures in quiescence that lose material in
+    /// the exchange that follows
+    pub see: bool,
+    /// skip captures in quiescence that can't bring the static evaluation back up to alpha
+    pub delta: bool,
+}
+
+impl Pruning {
+    /// Plain alpha-beta, every move searched to full depth
+    pub const NONE: Self = Self {
+        null_move: false,
+        late_move_reductions: false,
+        reverse_futility: false,
+        futility: false,
+        razoring: false,
+        late_move_pruning: false,
+        see: false,
+        delta: false,
+    };
+}
+
+impl Default for Pruning {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            futility: true,
+            razoring: true,
+            late_move_pruning: true,
+            see: true,
+            delta: true,
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            hash_size_mb: 16,
+            threads: 1,
+            multi_pv: 1,
+            move_overhead: Duration::from_millis(10),
+            quiescence_checks: true,
+            contempt: 0,
+            chess960: false,
+            pruning: Pruning::default(),
+        }
+    }
+}
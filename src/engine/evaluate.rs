@@ -8,50 +8,138 @@ use crate::{
             KNIGHT_VALUE, MATERIAL_WEIGHT, PAWN_POSITIONAL, PAWN_VALUE, POSITIONAL_WEIGHT,
             QUEEN_POSITIONAL, QUEEN_VALUE, ROOK_POSITIONAL, ROOK_VALUE,
         },
-        is_draw_repetition, who2move,
+        is_draw_repetition,
+        score::Score,
+        who2move,
     },
-    moving::{Move, MoveType},
     piece::{Piece, PieceType, Side},
     position::Position,
 };
 use PieceType::*;
 
-pub fn evaluate(board: &SearchBoard, repetitions: &RepetitionHashmap, depth: i32) -> i64 {
-    let (pin_state, check_paths) = board.legal_data();
-    let is_check = check_paths.is_check();
-    let moves = board.find_all_moves(pin_state, check_paths, false);
+/// `ply` is the distance from the root of the search, to score a checkmate by. `draw` is the
+/// score of a draw for the side to move, the search's draw score so both agree.
+pub fn evaluate(
+    board: &SearchBoard,
+    repetitions: &RepetitionHashmap,
+    ply: usize,
+    draw: Score,
+) -> Score {
+    EvalBreakdown::new(board, repetitions, ply, draw).total()
+}
 
-    let mut side_dependent = eval_score(board);
-    side_dependent += eval_material(board);
+/// Same as `evaluate` for a position that is known not to be over, with its `mobility` legal
+/// moves already generated
+pub fn evaluate_ongoing(board: &SearchBoard, mobility: usize, is_check: bool) -> Score {
+    EvalBreakdown::ongoing(board, mobility, is_check).total()
+}
 
-    let mut side_agnostic = moves.len() as i64;
-    side_agnostic -= if is_check { 10 } else { 0 };
+/// The terms `evaluate` adds up, kept apart so they can be shown for debugging
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalBreakdown {
+    pub side: Side,
+    /// From white's point of view
+    pub material: i64,
+    /// From white's point of view
+    pub positional: i64,
+    /// From the side to move's point of view
+    pub mobility: i64,
+    /// From the side to move's point of view
+    pub check: i64,
+    /// Set if the game is over, replacing every other term. From the side to move's point of
+    /// view
+    pub outcome: Option<Score>,
+}
 
-    if let Some(outcome) = evaluate_outcome(board, repetitions, !moves.is_empty(), is_check, depth)
-    {
-        return -outcome.abs();
+impl EvalBreakdown {
+    pub fn new(
+        board: &SearchBoard,
+        repetitions: &RepetitionHashmap,
+        ply: usize,
+        draw: Score,
+    ) -> Self {
+        let (pin_state, check_paths) = board.legal_data();
+        let is_check = check_paths.is_check();
+        let moves = board.find_all_moves(pin_state, check_paths, false);
+
+        Self {
+            outcome: evaluate_outcome(board, repetitions, !moves.is_empty(), is_check, ply, draw),
+            ..Self::ongoing(board, moves.len(), is_check)
+        }
+    }
+
+    /// The terms of a position that isn't over, `mobility` is the number of legal moves
+    fn ongoing(board: &SearchBoard, mobility: usize, is_check: bool) -> Self {
+        Self {
+            side: board.side(),
+            material: eval_material(board),
+            positional: eval_score(board),
+            mobility: mobility as i64,
+            check: if is_check { -10 } else { 0 },
+            outcome: None,
+        }
     }
 
-    return side_dependent * if board.side() == Side::White { 1 } else { -1 } + side_agnostic;
+    /// The evaluation from the side to move's point of view
+    pub fn total(&self) -> Score {
+        if let Some(outcome) = self.outcome {
+            return outcome;
+        }
+        let side_dependent = self.material + self.positional;
+        Score::new(side_dependent * who2move(self.side) + self.mobility + self.check)
+    }
 }
 
+/// The score of a finished game for the side to move, checkmated `ply` plies from the root or
+/// drawn, which scores `draw`
 pub fn evaluate_outcome(
     board: &SearchBoard,
     repetitions: &RepetitionHashmap,
     are_there_moves: bool,
     is_check: bool,
-    depth: i32,
-) -> Option<i64> {
+    ply: usize,
+    draw: Score,
+) -> Option<Score> {
     Some(
         match outcome(&board, are_there_moves, is_check, &repetitions) {
             Outcome::Ongoing => return None,
-            Outcome::WhiteWon => i64::MAX - 10000 + (100 * depth) as i64,
-            Outcome::BlackWon => i64::MIN + 10000 - (100 * depth) as i64,
-            // deduct points for being boring
-            Outcome::Stalemate => -1000 * who2move(board.side()),
+            // only the side to move can be checkmated
+            Outcome::WhiteWon | Outcome::BlackWon => Score::mated_in(ply),
+            Outcome::Stalemate => draw,
         },
     )
 }
+/// Converts centipawns into the units the evaluation works in
+pub fn centipawns_to_eval(centipawns: i64) -> i64 {
+    centipawns * PAWN_VALUE * MATERIAL_WEIGHT / 100
+}
+
+pub fn eval_to_centipawns(eval: i64) -> i64 {
+    eval * 100 / (PAWN_VALUE * MATERIAL_WEIGHT)
+}
+
+/// Material of the pieces of `side` other than pawns and the king, in pawns
+pub fn non_pawn_material(board: &SearchBoard, side: Side) -> i64 {
+    let bitboards = board.side_bitboards(side);
+    bitboards[KNIGHT].count_ones() as i64 * KNIGHT_VALUE
+        + bitboards[BISHOP].count_ones() as i64 * BISHOP_VALUE
+        + bitboards[ROOK].count_ones() as i64 * ROOK_VALUE
+        + bitboards[QUEEN].count_ones() as i64 * QUEEN_VALUE
+}
+
+/// Whether no pawn of the other side stands in front of the pawn of `side` on `pawn`, on its
+/// own file or the ones next to it
+pub fn is_passed_pawn(board: &SearchBoard, pawn: Position, side: Side) -> bool {
+    const FILE: u64 = 0x0101_0101_0101_0101;
+    let files =
+        (pawn.x().saturating_sub(1)..=(pawn.x() + 1).min(7)).fold(0, |files, x| files | FILE << x);
+    let ahead = match side {
+        Side::White => u64::MAX.checked_shl((pawn.y() as u32 + 1) * 8).unwrap_or(0),
+        Side::Black => (1 << (pawn.y() as u32 * 8)) - 1,
+    };
+    board.side_bitboards(side.opposite())[PAWN] & files & ahead == 0
+}
+
 pub fn eval_material(board: &SearchBoard) -> i64 {
     let mut eval = 0;
     eval += board.side_bitboards(Side::White)[PAWN].count_ones() as i64 * PAWN_VALUE;
@@ -163,26 +251,6 @@ pub(crate) const fn get_raw_positional(piece: Piece, pos: Position) -> i64 {
         King => KING_POSITIONAL,
     })[lookup_pos.index() as usize]
 }
-pub(super) fn rate_move(mov: &Move, who_to_move: Side) -> i64 {
-    let piece = mov.piece_type().with_side(who_to_move);
-    match mov.move_type {
-        MoveType::Normal(_) => {
-            let mut eval = 0;
-            eval -= get_raw_positional(piece, mov.from);
-            eval += get_raw_positional(piece, mov.to);
-            eval *= POSITIONAL_WEIGHT;
-            if let Some(taken) = mov.take {
-                eval += get_raw_material(taken.role()) * MATERIAL_WEIGHT;
-            }
-            eval
-        }
-        MoveType::Promotion(promoted_to) => get_raw_material(promoted_to).pow(2),
-        MoveType::ShortCastle => 20000,
-        MoveType::LongCastle => 20000,
-        MoveType::EnPassant => 1_000_000,
-    }
-}
-
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Outcome {
@@ -4,13 +4,20 @@ use nohash_hasher::BuildNoHashHasher;
 
 use crate::{board::SearchBoard, moving::Move, piece::Side};
 
+pub mod config;
 pub mod constants;
 #[allow(dead_code)]
 pub mod evaluate;
+pub mod move_ordering;
 // pub mod negamax;
 #[allow(dead_code)]
 pub mod bot;
+pub mod score;
+pub mod search_info;
+pub mod search_limits;
 pub mod searcher;
+pub mod stop_token;
+pub mod time_manager;
 pub mod transposition_table;
 
 pub use bot::Bot;
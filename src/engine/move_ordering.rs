@@ -0,0 +1,159 @@
+use crate::{
+    board::BoardState,
+    engine::evaluate::get_raw_material,
+    moving::{Move, MoveType},
+    piece::{PieceType, Side},
+};
+
+// the tiers moves are sorted into, the best first
+const HASH_MOVE: i64 = 1 << 40;
+const CAPTURE: i64 = 1 << 32;
+const FIRST_KILLER: i64 = 1 << 30;
+const SECOND_KILLER: i64 = FIRST_KILLER - 1;
+const COUNTERMOVE: i64 = FIRST_KILLER - 2;
+/// Captures that lose material come after every quiet move
+const LOSING_CAPTURE: i64 = -CAPTURE;
+/// History scores stay within +-this, so they never reach the killers
+pub const MAX_HISTORY: i32 = 1 << 14;
+
+/// What the search has learned about which moves cause cutoffs. Kept by the bot between
+/// searches, the history fades a bit with every new search.
+#[derive(Clone)]
+pub struct MoveOrdering {
+    /// two quiet moves per ply that recently caused a beta cutoff
+    killers: Vec<[Option<Move>; 2]>,
+    /// butterfly table: how well a quiet move did, by side, from and to square
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    /// the quiet move that refuted a move, by from and to square of the refuted move
+    countermoves: Box<[[Option<Move>; 64]; 64]>,
+}
+
+impl MoveOrdering {
+    pub fn new() -> Self {
+        Self {
+            killers: Vec::new(),
+            history: Box::new([[[0; 64]; 64]; 2]),
+            countermoves: Box::new([[None; 64]; 64]),
+        }
+    }
+
+    /// Prepares the tables for the next search. The killers of the last one were found at
+    /// other plies, the history is halved so the new position can overrule it.
+    pub fn age(&mut self) {
+        self.killers.clear();
+        for value in self.history.iter_mut().flatten().flatten() {
+            *value /= 2;
+        }
+    }
+
+    /// Sort key of `mov` in `board` at `ply`, higher is searched first: the hash move, captures
+    /// that don't lose material by MVV-LVA, killers, the countermove to `previous`, the other
+    /// quiet moves by history, then the losing captures
+    pub fn score(
+        &self,
+        board: &BoardState,
+        mov: &Move,
+        ply: usize,
+        hash_move: Option<Move>,
+        previous: Option<Move>,
+    ) -> i64 {
+        if hash_move == Some(*mov) {
+            return HASH_MOVE;
+        }
+        if !is_quiet(mov) {
+            let tier = if board.see_ge(mov, 0) {
+                CAPTURE
+            } else {
+                LOSING_CAPTURE
+            };
+            return tier + mvv_lva(mov);
+        }
+        let side = board.side();
+        let killers = self.killers.get(ply).copied().unwrap_or_default();
+        if killers[0] == Some(*mov) {
+            FIRST_KILLER
+        } else if killers[1] == Some(*mov) {
+            SECOND_KILLER
+        } else if previous.is_some_and(|previous| self.countermove(previous) == Some(*mov)) {
+            COUNTERMOVE
+        } else {
+            self.history(mov, side) as i64
+        }
+    }
+
+    /// Records the quiet move `mov` failing high at `ply`, after the quiet moves in `tried`
+    /// did not
+    pub fn update(
+        &mut self,
+        mov: Move,
+        side: Side,
+        ply: usize,
+        depth: i32,
+        previous: Option<Move>,
+        tried: &[Move],
+    ) {
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
+        }
+        if let Some(previous) = previous {
+            self.countermoves[previous.from.index() as usize][previous.to.index() as usize] =
+                Some(mov);
+        }
+        let bonus = (depth * depth).min(MAX_HISTORY);
+        self.add_history(mov, side, bonus);
+        for tried in tried {
+            self.add_history(*tried, side, -bonus);
+        }
+    }
+
+    /// How well the quiet move `mov` did so far, within +-`MAX_HISTORY`
+    pub fn history(&self, mov: &Move, side: Side) -> i32 {
+        self.history[side_index(side)][mov.from.index() as usize][mov.to.index() as usize]
+    }
+
+    fn countermove(&self, previous: Move) -> Option<Move> {
+        self.countermoves[previous.from.index() as usize][previous.to.index() as usize]
+    }
+
+    /// Moves the score towards the bound on the side of `bonus`, the closer it already is the
+    /// smaller the step, so it never leaves +-`MAX_HISTORY`
+    fn add_history(&mut self, mov: Move, side: Side, bonus: i32) {
+        let value =
+            &mut self.history[side_index(side)][mov.from.index() as usize][mov.to.index() as usize];
+        *value += bonus - *value * bonus.abs() / MAX_HISTORY;
+    }
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `mov` leaves the material alone, captures and promotions are ordered by MVV-LVA
+pub fn is_quiet(mov: &Move) -> bool {
+    mov.take.is_none() && !matches!(mov.move_type, MoveType::Promotion(_) | MoveType::EnPassant)
+}
+
+fn side_index(side: Side) -> usize {
+    match side {
+        Side::White => 0,
+        Side::Black => 1,
+    }
+}
+
+/// Most valuable victim first, among those the least valuable attacker
+fn mvv_lva(mov: &Move) -> i64 {
+    let victim = match (mov.take, mov.move_type) {
+        (Some(taken), _) => get_raw_material(taken.role()),
+        (None, MoveType::EnPassant) => get_raw_material(PieceType::Pawn),
+        (None, _) => 0,
+    };
+    let promotion = mov.promote_to().map_or(0, get_raw_material);
+    (victim + promotion) * 256 - get_raw_material(mov.piece_type())
+}
@@ -0,0 +1,111 @@
+use std::{
+    fmt,
+    ops::{Add, Neg, Sub},
+};
+
+use crate::engine::evaluate::eval_to_centipawns;
+
+/// Score of being checkmated on the board, every ply to go makes it one less extreme
+const MATE: i64 = 1_000_000_000;
+/// Farthest mate in plies the encoding tells apart from a plain evaluation
+const MAX_MATE_PLY: i64 = 10_000;
+
+/// How good a position is for the side to move, in the units of the evaluation. A forced mate
+/// scores `MATE` less the plies from the root of the search to the mate, so nearer mates
+/// score better and the distance can be read back.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct Score(i64);
+
+impl Score {
+    pub const ZERO: Score = Score(0);
+    /// Beyond every real score, for a full window
+    pub const INFINITE: Score = Score(MATE + 1);
+
+    /// A plain evaluation, far enough from the mate scores
+    pub const fn new(eval: i64) -> Self {
+        Self(eval)
+    }
+
+    /// The side to move mates `ply` plies from the root
+    pub const fn mate_in(ply: usize) -> Self {
+        Self(MATE - ply as i64)
+    }
+
+    /// The side to move is checkmated `ply` plies from the root
+    pub const fn mated_in(ply: usize) -> Self {
+        Self(-MATE + ply as i64)
+    }
+
+    pub const fn value(self) -> i64 {
+        self.0
+    }
+
+    /// Whether this is a forced mate for either side, or beyond
+    pub const fn is_mate(self) -> bool {
+        self.0.abs() > MATE - MAX_MATE_PLY
+    }
+
+    /// Moves (not plies) until mate from the root, negative if the side to move gets mated
+    pub fn mate_moves(self) -> Option<i64> {
+        if !self.is_mate() {
+            return None;
+        }
+        let plies = MATE - self.0.abs();
+        let moves = (plies + 1) / 2;
+        Some(if self.0 > 0 { moves } else { -moves })
+    }
+
+    /// The score to store for a node `ply` plies from the root: the transposition table counts
+    /// a mate from the node, since the position may come up again at another ply
+    pub fn to_tt(self, ply: usize) -> Self {
+        match self.0 {
+            score if score > MATE - MAX_MATE_PLY => Self(score + ply as i64),
+            score if score < -MATE + MAX_MATE_PLY => Self(score - ply as i64),
+            _ => self,
+        }
+    }
+
+    /// Reverses `to_tt` for a node `ply` plies from the root
+    pub fn from_tt(self, ply: usize) -> Self {
+        match self.0 {
+            score if score > MATE - MAX_MATE_PLY => Self(score - ply as i64),
+            score if score < -MATE + MAX_MATE_PLY => Self(score + ply as i64),
+            _ => self,
+        }
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+/// Shifts the score by a margin in evaluation units
+impl Add<i64> for Score {
+    type Output = Score;
+
+    fn add(self, margin: i64) -> Score {
+        Score(self.0 + margin)
+    }
+}
+
+impl Sub<i64> for Score {
+    type Output = Score;
+
+    fn sub(self, margin: i64) -> Score {
+        Score(self.0 - margin)
+    }
+}
+
+/// In the notation of UCI's `info score`: `cp 35` or `mate -3`
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mate_moves() {
+            Some(moves) => write!(f, "mate {}", moves),
+            None => write!(f, "cp {}", eval_to_centipawns(self.0)),
+        }
+    }
+}
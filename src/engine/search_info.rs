@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use crate::{
+    engine::{evaluate::eval_to_centipawns, score::Score},
+    moving::Move,
+};
+
+/// Progress report of a running search. Every field is optional so the same type can describe
+/// both a finished iteration and a short `currmove` update.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchInfo {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    /// rank of the line among the best ones, only set when more than one is searched for
+    pub multipv: Option<u32>,
+    pub score: Option<InfoScore>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub time: Option<Duration>,
+    pub hashfull: Option<u32>,
+    pub currmove: Option<Move>,
+    pub currmovenumber: Option<u32>,
+    pub pv: Vec<Move>,
+}
+
+/// What a finished search found
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Move,
+    /// score of `best_move` from the side to move's point of view
+    pub score: Score,
+    /// the line the engine expects, starting with `best_move`
+    pub pv: Vec<Move>,
+    /// last depth that was searched to the end, 0 if the search was stopped before that
+    pub depth: i32,
+    pub nodes: u64,
+    /// every move scoring as well as `best_move`, `best_move` first
+    pub best_moves: Vec<Move>,
+    /// the `SearchConfig::multi_pv` best root moves with their exact scores, best first
+    pub lines: Vec<PvLine>,
+}
+
+/// A root move among the best ones, with the line the engine expects after it
+#[derive(Clone, Debug, PartialEq)]
+pub struct PvLine {
+    pub score: Score,
+    /// starts with the root move
+    pub pv: Vec<Move>,
+}
+
+impl SearchResult {
+    /// The reply the engine expects to `best_move`, the move to ponder on
+    pub fn ponder_move(&self) -> Option<Move> {
+        self.pv.get(1).copied()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InfoScore {
+    Centipawns(i64),
+    /// Moves (not plies) until mate, negative if the engine is getting mated
+    Mate(i64),
+}
+
+impl From<Score> for InfoScore {
+    /// Converts a score of the root position
+    fn from(score: Score) -> Self {
+        match score.mate_moves() {
+            Some(moves) => InfoScore::Mate(moves),
+            None => InfoScore::Centipawns(eval_to_centipawns(score.value())),
+        }
+    }
+}
+
+pub fn nodes_per_second(nodes: u64, elapsed: Duration) -> u64 {
+    let micros = elapsed.as_micros().max(1);
+    (nodes as u128 * 1_000_000 / micros) as u64
+}
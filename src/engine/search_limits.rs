@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use crate::{
+    engine::{score::Score, searcher::MAX_DEPTH, time_manager::TimeManager},
+    moving::Move,
+};
+
+/// What ends a search and which root moves it looks at. Starts from `depth`, `nodes`,
+/// `move_time`, `mate` or `infinite` and is narrowed down by the other builder methods, e.g.
+/// `SearchLimits::depth(8).with_nodes(100_000)`. Whichever limit is hit first ends the search.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    /// deepest iteration, `None` to keep deepening until another limit is hit
+    pub depth: Option<i32>,
+    /// nodes of every thread together, checked while searching so the search stays
+    /// reproducible
+    pub nodes: Option<u64>,
+    /// fixed time for the move, the move overhead of the config is subtracted
+    pub move_time: Option<Duration>,
+    /// the clock, for searches that plan their own time
+    pub time: Option<TimeManager>,
+    /// stop as soon as a mate in this many moves or less is found
+    pub mate: Option<u32>,
+    /// ignore every other limit, only the stop token ends the search
+    pub infinite: bool,
+    /// only these root moves are searched, all of them if empty
+    pub search_moves: Vec<Move>,
+    /// root moves left out of the search
+    pub excluded_moves: Vec<Move>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: i32) -> Self {
+        Self::default().with_depth(depth)
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Self::default().with_nodes(nodes)
+    }
+
+    pub fn move_time(move_time: Duration) -> Self {
+        Self::default().with_move_time(move_time)
+    }
+
+    /// Searches for a mate in `moves` moves, as deep as that takes
+    pub fn mate(moves: u32) -> Self {
+        Self::default().with_mate(moves)
+    }
+
+    pub fn infinite() -> Self {
+        Self {
+            infinite: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_depth(self, depth: i32) -> Self {
+        Self {
+            depth: Some(depth.max(1)),
+            ..self
+        }
+    }
+
+    pub fn with_nodes(self, nodes: u64) -> Self {
+        Self {
+            nodes: Some(nodes),
+            ..self
+        }
+    }
+
+    pub fn with_move_time(self, move_time: Duration) -> Self {
+        Self {
+            move_time: Some(move_time),
+            ..self
+        }
+    }
+
+    pub fn with_time(self, time: TimeManager) -> Self {
+        Self {
+            time: Some(time),
+            ..self
+        }
+    }
+
+    pub fn with_mate(self, moves: u32) -> Self {
+        Self {
+            mate: Some(moves.max(1)),
+            ..self
+        }
+    }
+
+    pub fn with_search_moves(self, search_moves: Vec<Move>) -> Self {
+        Self {
+            search_moves,
+            ..self
+        }
+    }
+
+    pub fn with_excluded_moves(self, excluded_moves: Vec<Move>) -> Self {
+        Self {
+            excluded_moves,
+            ..self
+        }
+    }
+
+    /// The deepest iteration to start, a mate in n moves is found n * 2 - 1 plies deep
+    pub fn max_depth(&self) -> i32 {
+        if self.infinite {
+            return MAX_DEPTH;
+        }
+        let mate_depth = self.mate.map(|moves| (moves.max(1) * 2 - 1) as i32);
+        self.depth
+            .or(mate_depth)
+            .unwrap_or(MAX_DEPTH)
+            .clamp(1, MAX_DEPTH)
+    }
+
+    /// Whether the root move `mov` is part of the search
+    pub fn allows(&self, mov: &Move) -> bool {
+        (self.search_moves.is_empty() || self.search_moves.contains(mov))
+            && !self.excluded_moves.contains(mov)
+    }
+
+    /// Whether `nodes` used up the node budget
+    pub fn nodes_exhausted(&self, nodes: u64) -> bool {
+        !self.infinite && self.nodes.is_some_and(|limit| nodes >= limit)
+    }
+
+    /// Whether `score` is the mate that was asked for, or a faster one
+    pub fn mate_found(&self, score: Score) -> bool {
+        !self.infinite
+            && self.mate.is_some_and(|moves| {
+                score
+                    .mate_moves()
+                    .is_some_and(|found| found > 0 && found <= moves as i64)
+            })
+    }
+}
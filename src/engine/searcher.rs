@@ -1,32 +1,108 @@
 use std::{
     cmp,
     collections::{HashMap, hash_map::Entry},
-    sync::{Arc, Mutex},
+    sync::Arc,
+    time::Instant,
 };
 
+use arrayvec::ArrayVec;
 use nohash_hasher::BuildNoHashHasher;
 
 use crate::{
     board::SearchBoard,
     engine::{
         RepetitionHashmap,
-        evaluate::{evaluate, evaluate_outcome, rate_move},
-        transposition_table::{NodeType, TTableEntry, TranspositionTable},
+        config::Pruning,
+        evaluate::{
+            Outcome, centipawns_to_eval, evaluate_ongoing, is_passed_pawn, non_pawn_material,
+            outcome,
+        },
+        move_ordering::{MAX_HISTORY, MoveOrdering, is_quiet},
+        score::Score,
+        stop_token::StopToken,
+        transposition_table::{NodeType, TranspositionTable},
     },
-    moving::{Move, Unmove},
+    moving::{Move, MoveType, Unmove},
+    piece::{PieceType, Side},
+    see::material_gain,
 };
 
+/// Deepest search the root driver will ever ask for
+pub const MAX_DEPTH: i32 = 64;
+/// Nodes searched between two looks at the clock
+const CLOCK_CHECK_INTERVAL: u64 = 1024;
+
+// selective search, the margins are in centipawns per ply of depth left
+const REVERSE_FUTILITY_DEPTH: i32 = 6;
+const REVERSE_FUTILITY_MARGIN: i64 = 80;
+const RAZORING_DEPTH: i32 = 2;
+const RAZORING_MARGIN: i64 = 300;
+const FUTILITY_DEPTH: i32 = 3;
+const FUTILITY_MARGIN: i64 = 100;
+const LATE_MOVE_DEPTH: i32 = 3;
+const NULL_MOVE_DEPTH: i32 = 3;
+/// Plies the null move is searched less deep than the other moves, on top of a quarter of
+/// the depth
+const NULL_MOVE_REDUCTION: i32 = 2;
+/// Pieces (in pawns) the side to move needs not to be suspected of zugzwang, a rook or a
+/// minor piece is not enough
+const ZUGZWANG_MATERIAL: i64 = 5;
+/// Depth from which the hash move is checked for being singular
+const SINGULAR_DEPTH: i32 = 6;
+/// How much (in centipawns per ply of depth) every other move has to be worse than the hash
+/// move for it to be singular
+const SINGULAR_MARGIN: i64 = 4;
+/// Depth up to which quiet moves losing material are skipped, if they lose more than the
+/// margin per ply of depth left
+const SEE_QUIET_DEPTH: i32 = 4;
+const SEE_QUIET_MARGIN: i64 = 50;
+const REDUCTION_DEPTH: i32 = 3;
+/// Centipawns a capture in quiescence may gain on top of the material it takes, it is pruned
+/// if even that leaves the static evaluation at or below alpha
+const DELTA_MARGIN: i64 = 200;
+/// Moves searched to full depth before the reductions start
+const REDUCTION_MOVES: usize = 3;
+
 pub struct SearchContext {
     // general
     pub board: SearchBoard,
     pub repetitions: RepetitionHashmap,
     pub evaluated_move: Move,
 
-    pub ttable: Arc<Mutex<TranspositionTable>>,
-    pub nodes_searched: u32,
+    pub ttable: Arc<TranspositionTable>,
+    /// once set, every node returns immediately and the result of the search is meaningless
+    pub stop: StopToken,
+    /// the search is abandoned once this has passed, unless the stop token is pondering
+    pub deadline: Option<Instant>,
+    /// the search is abandoned once it searched this many nodes, even while pondering
+    pub node_limit: Option<u64>,
+    /// set once the deadline passed or the node limit was reached
+    pub timed_out: bool,
+    pub nodes_searched: u64,
+    /// deepest ply reached from the root, quiescence included
+    pub seldepth: i32,
+    /// triangular PV table, the line from the node `ply` plies from the root is at index `ply`
+    pv_table: Vec<Vec<Move>>,
+    /// killers, history and countermoves, handed from one root move to the next
+    pub ordering: MoveOrdering,
+
+    // selective search
+    pub(super) pruning: Pruning,
+    /// set while a null move cutoff is verified, no null moves are tried then
+    verifying: bool,
+    /// the move the next node leaves out, to find out whether it is singular
+    excluded: Option<Move>,
+    /// plies the moves on the current line were extended by
+    line_extensions: i32,
+    root_depth: i32,
+
+    // draws
+    pub(super) contempt: Score,
+    root_side: Side,
 
     // quiescence
-    pub(super) quiescence_depth_limit: i32,
+    /// search the quiet moves giving check at the first ply of quiescence
+    pub(super) quiescence_checks: bool,
 }
 
 impl SearchContext {
@@ -34,16 +110,32 @@ impl SearchContext {
         mut board: SearchBoard,
         repetitions: RepetitionHashmap,
         evaluated_move: Move,
-        ttable: Arc<Mutex<TranspositionTable>>,
+        ttable: Arc<TranspositionTable>,
+        stop: StopToken,
     ) -> Self {
+        let root_side = board.side();
         board.make(&evaluated_move);
         Self {
             board,
             repetitions,
             evaluated_move,
             nodes_searched: 0,
+            seldepth: 0,
+            pv_table: Vec::new(),
+            ordering: MoveOrdering::new(),
+            pruning: Pruning::default(),
+            verifying: false,
+            excluded: None,
+            line_extensions: 0,
+            root_depth: 0,
             ttable,
-            quiescence_depth_limit: 2,
+            stop,
+            deadline: None,
+            node_limit: None,
+            timed_out: false,
+            contempt: Score::ZERO,
+            root_side,
+            quiescence_checks: true,
         }
     }
 
@@ -51,47 +143,227 @@ impl SearchContext {
         return &self.board;
     }
 
-    pub fn evaluate(&mut self, min_depth: i32, _max_depth: i32) -> (Move, i64) {
-        let eval = self.evaluate_inner(min_depth, i64::MIN + 1, i64::MAX);
+    /// Whether the search has to be abandoned, because it was stopped or ran out of time or
+    /// nodes
+    fn is_aborted(&mut self) -> bool {
+        if self
+            .node_limit
+            .is_some_and(|limit| self.nodes_searched >= limit)
+        {
+            self.timed_out = true;
+        }
+        if !self.timed_out
+            && self.nodes_searched.is_multiple_of(CLOCK_CHECK_INTERVAL)
+            && let Some(deadline) = self.deadline
+        {
+            self.timed_out = !self.stop.is_pondering() && Instant::now() >= deadline;
+        }
+        self.timed_out || self.stop.is_stopped()
+    }
+
+    /// The line the last search expects to be played, starting with `evaluated_move`. Cut short
+    /// where the rest came from the transposition table.
+    pub fn pv(&self) -> Vec<Move> {
+        // the node after `evaluated_move` is one ply from the root
+        let line = self.pv_table.get(1).map_or(&[][..], |line| &line[..]);
+        std::iter::once(self.evaluated_move)
+            .chain(line.iter().copied())
+            .collect()
+    }
+
+    /// Forgets the line of the node at `ply`, it is about to be searched again
+    fn clear_pv(&mut self, ply: usize) {
+        if self.pv_table.len() <= ply {
+            self.pv_table.resize_with(ply + 1, Vec::new);
+        }
+        self.pv_table[ply].clear();
+    }
+
+    /// The best line at `ply` is now `mov` followed by the line of the child just searched
+    fn update_pv(&mut self, ply: usize, mov: Move) {
+        let (parents, children) = self.pv_table.split_at_mut(ply + 1);
+        let line = &mut parents[ply];
+        line.clear();
+        line.push(mov);
+        if let Some(child) = children.first() {
+            line.extend_from_slice(child);
+        }
+    }
+
+    /// Score of a draw for the side to move, drawing is `contempt` worse than equal for the root
+    fn draw_score(&self) -> Score {
+        if self.board.side() == self.root_side {
+            -self.contempt
+        } else {
+            self.contempt
+        }
+    }
+
+    /// Score of `evaluated_move` from the root side's point of view, clamped to the window
+    /// between `alpha` and `beta`
+    pub fn evaluate(&mut self, depth: i32, alpha: Score, beta: Score) -> (Move, Score) {
+        self.root_depth = depth;
+        // the root move has already been made, the search starts one ply from the root
+        let eval = self.evaluate_inner(depth, 1, -beta, -alpha, Some(self.evaluated_move));
 
         return (self.evaluated_move, -eval);
     }
 
-    fn evaluate_inner(&mut self, depth: i32, mut alpha: i64, beta: i64) -> i64 {
-        if let Some(transposition_score) =
-            self.ttable
-                .lock()
-                .unwrap()
-                .get(self.board().zobrist, depth, alpha, beta)
-        {
-            return transposition_score;
+    /// `previous` is the move that led to this position, `None` after a null move. `ply` counts
+    /// the plies from the root, `depth` the plies left, which reductions make smaller and
+    /// extensions larger.
+    ///
+    /// Principal variation search: the first move is searched with the full window, the rest
+    /// only to prove they are worse with a zero window, and again with the full window if one
+    /// turns out better.
+    fn evaluate_inner(
+        &mut self,
+        depth: i32,
+        ply: usize,
+        mut alpha: Score,
+        mut beta: Score,
+        previous: Option<Move>,
+    ) -> Score {
+        // only meant for this node, not the ones below
+        let excluded = self.excluded.take();
+        self.clear_pv(ply);
+        if self.is_aborted() {
+            return Score::ZERO;
         }
-        if depth == 0 {
-            return self.quiesce(0, alpha, beta);
+        // mate distance pruning: no line from here mates sooner than being mated right now or
+        // mating with the next move
+        alpha = alpha.max(Score::mated_in(ply));
+        beta = beta.min(Score::mate_in(ply + 1));
+        if alpha >= beta {
+            return alpha;
         }
+        let hash_entry = {
+            let ttable = &self.ttable;
+            // without the excluded move the stored score is about a different set of moves
+            if excluded.is_none()
+                && let Some(transposition_score) =
+                    ttable.get(self.board().zobrist, ply, depth, alpha, beta)
+            {
+                return transposition_score;
+            }
+            ttable.entry(self.board().zobrist, ply)
+        };
+        if depth <= 0 {
+            return self.quiesce(ply, 0, alpha, beta);
+        }
+        self.seldepth = cmp::max(self.seldepth, ply as i32);
 
         let (pin_state, check_paths) = self.board().legal_data();
         let is_check = check_paths.is_check();
         let mut moves = self.board().find_all_moves(pin_state, check_paths, false);
+        let hash_move = hash_entry.and_then(|entry| entry.best_move?.find_in(&moves));
         // outcome
-        if let Some(eval) = evaluate_outcome(
-            self.board(),
-            &self.repetitions,
-            !moves.is_empty(),
-            is_check,
-            depth,
-        ) {
-            let eval = eval;
+        match outcome(self.board(), !moves.is_empty(), is_check, &self.repetitions) {
+            Outcome::Ongoing => {}
+            Outcome::Stalemate => return self.draw_score(),
             // this code runs when the side to play is checkmated -> negative
-            return -eval.abs();
+            _ => return Score::mated_in(ply),
         }
-        moves.sort_by_cached_key(|mov| -rate_move(mov, self.board().side()));
-        let mut eval = i64::MIN + 1;
-        let mut node_type = NodeType::UpperBound;
-
         self.nodes_searched += 1;
 
+        let side = self.board().side();
+        let static_eval = evaluate_ongoing(self.board(), moves.len(), is_check);
+        let pv_node = beta > alpha + 1;
+        // the margins mean nothing when a mate is in the window, and in check every move counts
+        let selective = !is_check && !alpha.is_mate() && !beta.is_mate();
+        // zero window nodes away from the principal variation may be cut off as a whole, and lose
+        // their late quiet moves
+        let prunable = selective && !pv_node && excluded.is_none();
+        let pruning = self.pruning;
+
+        if prunable
+            && pruning.reverse_futility
+            && depth <= REVERSE_FUTILITY_DEPTH
+            && static_eval - centipawns_to_eval(REVERSE_FUTILITY_MARGIN * depth as i64) >= beta
+        {
+            return beta;
+        }
+        if prunable
+            && pruning.razoring
+            && depth <= RAZORING_DEPTH
+            && static_eval + centipawns_to_eval(RAZORING_MARGIN * depth as i64) <= alpha
+            && self.quiesce(ply, 0, alpha, alpha + 1) <= alpha
+        {
+            return alpha;
+        }
+        if prunable
+            && pruning.null_move
+            && depth >= NULL_MOVE_DEPTH
+            && previous.is_some()
+            && !self.verifying
+            && static_eval >= beta
+            && non_pawn_material(self.board(), side) > 0
+            && self.null_move_fails_high(depth, ply, beta, previous)
+        {
+            return beta;
+        }
+
+        // the hash move is singular if every other move is clearly worse than its stored score
+        let singular = match hash_entry {
+            Some(entry)
+                if excluded.is_none()
+                    && depth >= SINGULAR_DEPTH
+                    && hash_move.is_some()
+                    && entry.depth >= depth - 3
+                    && !matches!(entry.node_type, NodeType::UpperBound)
+                    && !entry.score.is_mate() =>
+            {
+                let singular_beta =
+                    entry.score - centipawns_to_eval(SINGULAR_MARGIN * depth as i64);
+                self.excluded = hash_move;
+                let score = self.evaluate_inner(
+                    (depth - 1) / 2,
+                    ply,
+                    singular_beta - 1,
+                    singular_beta,
+                    previous,
+                );
+                // the line belongs to the search without the hash move
+                self.clear_pv(ply);
+                score < singular_beta
+            }
+            _ => false,
+        };
+
+        let board = &self.board;
+        moves.sort_by_cached_key(|mov| -self.ordering.score(board, mov, ply, hash_move, previous));
+        let futile = prunable
+            && pruning.futility
+            && depth <= FUTILITY_DEPTH
+            && static_eval + centipawns_to_eval(FUTILITY_MARGIN * depth as i64) <= alpha;
+        let late_quiets = if prunable && pruning.late_move_pruning && depth <= LATE_MOVE_DEPTH {
+            (3 + depth * depth) as usize
+        } else {
+            usize::MAX
+        };
+        let mut eval = -Score::INFINITE;
+        let mut node_type = NodeType::UpperBound;
+        let mut best_move = None;
+        // the quiet moves that didn't cut off, their history suffers if a later one does
+        let mut quiets_tried = ArrayVec::<Move, 219>::new();
+        let mut searched = 0;
+
         for mov in moves {
+            if Some(mov) == excluded {
+                continue;
+            }
+            let index = searched;
+            searched += 1;
+            let quiet = is_quiet(&mov);
+            let history = self.ordering.history(&mov, side);
+            // a quiet move that hangs the piece it moves
+            let loses_material = prunable
+                && quiet
+                && pruning.see
+                && depth <= SEE_QUIET_DEPTH
+                && !self
+                    .board()
+                    .see_ge(&mov, -centipawns_to_eval(SEE_QUIET_MARGIN * depth as i64));
             let unmake = Unmove::new(mov, &self.board());
             self.board.make(&mov);
             let repetition = self.repetitions.entry(self.board().zobrist).or_insert(0);
@@ -100,9 +372,57 @@ impl SearchContext {
             if *repetition > 1 {
                 *repetition -= 1;
                 self.board.unmake(unmake);
-                return 0;
+                return self.draw_score();
             }
-            let score = -self.evaluate_inner(depth - 1, -beta, -alpha);
+            let gives_check = self.board().legal_data().1.is_check();
+            // the first move is always searched, checks are never cut short
+            let late_quiet = selective && quiet && index > 0 && !gives_check;
+            if late_quiet && (futile || loses_material || quiets_tried.len() >= late_quiets) {
+                let repetition = self.repetitions.entry(self.board().zobrist).or_insert(0);
+                *repetition -= 1;
+                self.board.unmake(unmake);
+                continue;
+            }
+
+            let extension = self.extension(
+                &mov,
+                side,
+                previous,
+                gives_check,
+                singular && Some(mov) == hash_move,
+            );
+            let reduction = if late_quiet
+                && pruning.late_move_reductions
+                && depth >= REDUCTION_DEPTH
+                && index >= REDUCTION_MOVES
+            {
+                late_move_reduction(depth, index, history)
+            } else {
+                0
+            };
+            let new_depth = depth - 1 + extension;
+            self.line_extensions += extension;
+            let score = if index == 0 {
+                -self.evaluate_inner(new_depth, ply + 1, -beta, -alpha, Some(mov))
+            } else {
+                let mut score = -self.evaluate_inner(
+                    new_depth - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    Some(mov),
+                );
+                // better than expected, the reduction may have hidden why
+                if score > alpha && reduction > 0 {
+                    score = -self.evaluate_inner(new_depth, ply + 1, -alpha - 1, -alpha, Some(mov));
+                }
+                // inside the window, its exact score is needed
+                if score > alpha && score < beta {
+                    score = -self.evaluate_inner(new_depth, ply + 1, -beta, -alpha, Some(mov));
+                }
+                score
+            };
+            self.line_extensions -= extension;
             eval = cmp::max(score, eval);
 
             // rebind it because of the borrow checker
@@ -110,74 +430,188 @@ impl SearchContext {
             *repetition -= 1;
             self.board.unmake(unmake);
 
+            // an aborted child returns garbage, keep it out of the table
+            if self.is_aborted() {
+                return Score::ZERO;
+            }
+
+            if alpha < score {
+                best_move = Some(mov);
+                self.update_pv(ply, mov);
+            }
+
             // fail high
             if eval >= beta {
-                node_type = NodeType::LowerBound;
-                self.ttable
-                    .lock()
-                    .unwrap()
-                    .insert(self.board().zobrist, eval, depth, node_type);
+                if quiet {
+                    self.ordering
+                        .update(mov, side, ply, depth, previous, &quiets_tried);
+                }
+                if excluded.is_none() {
+                    node_type = NodeType::LowerBound;
+                    self.ttable.insert(
+                        self.board().zobrist,
+                        ply,
+                        eval,
+                        depth,
+                        node_type,
+                        best_move,
+                    );
+                }
                 return beta;
             }
+            if quiet {
+                quiets_tried.push(mov);
+            }
 
             if alpha < eval {
                 node_type = NodeType::PV;
                 alpha = eval
             }
         }
-        self.ttable
-            .lock()
-            .unwrap()
-            .insert(self.board().zobrist, eval, depth, node_type);
+        if excluded.is_none() {
+            self.ttable
+                .insert(self.board().zobrist, ply, eval, depth, node_type, best_move);
+        }
         alpha
     }
 
-    fn quiesce(&mut self, descended: i32, mut alpha: i64, beta: i64) -> i64 {
-        self.nodes_searched += 1;
+    /// Plies `mov` is searched deeper than the other moves: checks, recaptures, pawns reaching
+    /// the seventh rank, passed pawns reaching the sixth and singular moves deserve a closer
+    /// look. One ply at most, and only while the line hasn't used up its budget of half the
+    /// root depth. Called with `mov` already made.
+    fn extension(
+        &self,
+        mov: &Move,
+        side: Side,
+        previous: Option<Move>,
+        gives_check: bool,
+        singular: bool,
+    ) -> i32 {
+        if self.line_extensions >= self.root_depth / 2 {
+            return 0;
+        }
+        let recapture = mov.take.is_some()
+            && previous.is_some_and(|previous| previous.take.is_some() && previous.to == mov.to);
+        // nothing can stop a pawn on the seventh from the front, it's always passed
+        let pawn_push = mov.move_type == MoveType::Normal(PieceType::Pawn)
+            && (mov.to.y() == side.pers_y(6)
+                || (mov.to.y() == side.pers_y(5) && is_passed_pawn(self.board(), mov.to, side)));
+        (gives_check || recapture || pawn_push || singular) as i32
+    }
 
-        if let Some(transposition_score) =
-            self.ttable
-                .lock()
-                .unwrap()
-                .get(self.board().zobrist, -descended, alpha, beta)
-        {
-            return transposition_score;
+    /// Whether the side to move is still at least at `beta` after passing the turn. With
+    /// little material left, where passing may be the best move there is, a shallow search
+    /// without null moves has to confirm it.
+    fn null_move_fails_high(
+        &mut self,
+        depth: i32,
+        ply: usize,
+        beta: Score,
+        previous: Option<Move>,
+    ) -> bool {
+        let side = self.board().side();
+        let reduced_depth = depth - 1 - NULL_MOVE_REDUCTION - depth / 4;
+        let unmake = self.board.make_null();
+        let score = -self.evaluate_inner(reduced_depth, ply + 1, -beta, -beta + 1, None);
+        self.board.unmake_null(unmake);
+        if score < beta || self.is_aborted() {
+            return false;
         }
-        if descended == self.quiescence_depth_limit {
-            return evaluate(self.board(), &self.repetitions, -descended);
+        if non_pawn_material(self.board(), side) > ZUGZWANG_MATERIAL {
+            return true;
+        }
+        self.verifying = true;
+        let verified = self.evaluate_inner(reduced_depth, ply, beta - 1, beta, previous);
+        self.verifying = false;
+        verified >= beta && !self.is_aborted()
+    }
+
+    /// Searches the moves that change the material until the position is quiet, so the static
+    /// evaluation isn't taken in the middle of an exchange. Unless in check, the side to move
+    /// may stand pat on the static evaluation instead. In check every evasion is searched.
+    /// `descended` counts the plies below the horizon, quiet checks are only tried at the first.
+    fn quiesce(&mut self, ply: usize, descended: i32, mut alpha: Score, beta: Score) -> Score {
+        self.clear_pv(ply);
+        if self.is_aborted() {
+            return Score::ZERO;
         }
+        self.nodes_searched += 1;
+        self.seldepth = cmp::max(self.seldepth, ply as i32);
+
+        let checks = self.quiescence_checks && descended == 0;
+        // below any full width search, a node that tried the checks is worth a bit more
+        let tt_depth = if checks { 0 } else { -1 };
+        let hash_move = {
+            let ttable = &self.ttable;
+            if let Some(transposition_score) =
+                ttable.get(self.board().zobrist, ply, tt_depth, alpha, beta)
+            {
+                return transposition_score;
+            }
+            ttable.best_move(self.board().zobrist)
+        };
 
         let (pin_state, check_paths) = self.board().legal_data();
         let is_check = check_paths.is_check();
-        let mut moves = self.board().find_all_moves(pin_state, check_paths, true);
+        let mut moves = self.board().find_all_moves(pin_state, check_paths, false);
         // outcome
-        if let Some(eval) = evaluate_outcome(
-            self.board(),
-            &self.repetitions,
-            !moves.is_empty(),
-            is_check,
-            -descended,
-        ) {
-            let eval = eval;
+        match outcome(self.board(), !moves.is_empty(), is_check, &self.repetitions) {
+            Outcome::Ongoing => {}
+            Outcome::Stalemate => return self.draw_score(),
             // this code runs when the side to play is checkmated -> negative
-            return -eval.abs();
+            _ => return Score::mated_in(ply),
         }
-        moves.sort_by_cached_key(|mov| -rate_move(mov, self.board().side()));
-        let mut eval = i64::MIN + 1;
+        let hash_move = hash_move.and_then(|packed| packed.find_in(&moves));
+        let mut eval = -Score::INFINITE;
         let mut node_type = NodeType::UpperBound;
 
+        let stand_pat = (!is_check).then(|| evaluate_ongoing(self.board(), moves.len(), false));
+        if let Some(stand_pat) = stand_pat {
+            if stand_pat >= beta {
+                return beta;
+            }
+            if alpha < stand_pat {
+                node_type = NodeType::PV;
+                alpha = stand_pat;
+            }
+            eval = stand_pat;
+            moves.retain(|mov| checks || !is_quiet(mov));
+        }
+        let board = &self.board;
+        moves.sort_by_cached_key(|mov| -self.ordering.score(board, mov, ply, hash_move, None));
+        let mut best_move = None;
+
         for mov in moves {
+            let quiet = is_quiet(&mov);
+            if let Some(stand_pat) = stand_pat {
+                // a move that loses material in the exchange won't fix anything
+                if self.pruning.see && !self.board().see_ge(&mov, 0) {
+                    continue;
+                }
+                // nor does a capture that can't lift the evaluation up to alpha
+                if self.pruning.delta
+                    && !quiet
+                    && stand_pat + material_gain(&mov) + centipawns_to_eval(DELTA_MARGIN) <= alpha
+                {
+                    continue;
+                }
+            }
             let unmake = Unmove::new(mov, &self.board());
             self.board.make(&mov);
+            // of the quiet moves only the checks are searched
+            if quiet && !is_check && !self.board().legal_data().1.is_check() {
+                self.board.unmake(unmake);
+                continue;
+            }
             let repetition = self.repetitions.entry(self.board().zobrist).or_insert(0);
             *repetition += 1;
 
             if *repetition > 1 {
                 *repetition -= 1;
                 self.board.unmake(unmake);
-                return 0;
+                return self.draw_score();
             }
-            let score = -self.quiesce(descended + 1, -beta, -alpha);
+            let score = -self.quiesce(ply + 1, descended + 1, -beta, -alpha);
             eval = cmp::max(score, eval);
 
             // rebind it because of the borrow checker
@@ -185,14 +619,26 @@ impl SearchContext {
             *repetition -= 1;
             self.board.unmake(unmake);
 
+            // an aborted child returns garbage, keep it out of the table
+            if self.is_aborted() {
+                return Score::ZERO;
+            }
+
+            if alpha < score {
+                best_move = Some(mov);
+                self.update_pv(ply, mov);
+            }
+
             // fail high
             if eval >= beta {
                 node_type = NodeType::LowerBound;
-                self.ttable.lock().unwrap().insert(
+                self.ttable.insert(
                     self.board().zobrist,
+                    ply,
                     eval,
-                    -descended,
+                    tt_depth,
                     node_type,
+                    best_move,
                 );
                 return beta;
             }
@@ -202,10 +648,21 @@ impl SearchContext {
                 alpha = eval
             }
         }
-        self.ttable
-            .lock()
-            .unwrap()
-            .insert(self.board().zobrist, eval, -descended, node_type);
+        self.ttable.insert(
+            self.board().zobrist,
+            ply,
+            eval,
+            tt_depth,
+            node_type,
+            best_move,
+        );
         alpha
     }
 }
+
+/// Plies a quiet move late in the move order is searched less deep: more the later it comes and
+/// the more depth is left, less if it did well before. At least one ply is always left.
+fn late_move_reduction(depth: i32, index: usize, history: i32) -> i32 {
+    let reduction = ((depth as f64).ln() * (index as f64).ln() / 2.0) as i32;
+    (reduction - history / (MAX_HISTORY / 2)).clamp(0, depth - 2)
+}
@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    engine::{
+        evaluate::{centipawns_to_eval, eval_to_centipawns},
+        score::Score,
+    },
+    moving::Move,
+};
+
+/// Moves the rest of the game is assumed to take when the GUI doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// Share of the clock a single move may use at most, the rest of the game needs the remainder
+const MAX_CLOCK_SHARE: f64 = 0.8;
+/// How many times the planned time a move may take before the search is aborted
+const MAX_OVERRUN: u32 = 5;
+/// Iterations in a row the best move has to survive to be considered clearly best
+const STABLE_ITERATIONS: u32 = 4;
+
+/// Splits the clock into a soft limit, after which no new iteration is started, and a hard
+/// limit, at which the running iteration is abandoned
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    /// time the move should take if the search goes as usual
+    optimum: Duration,
+    maximum: Duration,
+    /// a fixed move time, which isn't scaled by how settled the search is
+    fixed: bool,
+    // what the last iterations found, to tell how settled the search is
+    best_move: Option<Move>,
+    best_eval: Option<Score>,
+    instability: f64,
+    score_drop: f64,
+    stable_iterations: u32,
+}
+
+impl TimeManager {
+    /// Plans a move with `time_left` on the clock, `increment` added after the move and
+    /// `moves_to_go` until the next time control, if there is one
+    pub fn new(
+        time_left: Duration,
+        increment: Duration,
+        moves_to_go: Option<u32>,
+        move_overhead: Duration,
+    ) -> Self {
+        let available = time_left.saturating_sub(move_overhead);
+        let moves_to_go = moves_to_go
+            .unwrap_or(DEFAULT_MOVES_TO_GO)
+            .clamp(1, DEFAULT_MOVES_TO_GO);
+        let maximum = available.mul_f64(MAX_CLOCK_SHARE);
+        let optimum = (available / moves_to_go + increment * 3 / 4).min(maximum);
+        Self::with_limits(optimum, (optimum * MAX_OVERRUN).min(maximum), false)
+    }
+
+    /// A fixed time for the move, like `go movetime`, the search is neither stretched nor cut
+    /// short
+    pub fn fixed(move_time: Duration, move_overhead: Duration) -> Self {
+        let move_time = move_time.saturating_sub(move_overhead);
+        Self::with_limits(move_time, move_time, true)
+    }
+
+    fn with_limits(optimum: Duration, maximum: Duration, fixed: bool) -> Self {
+        Self {
+            start: Instant::now(),
+            optimum,
+            maximum,
+            fixed,
+            best_move: None,
+            best_eval: None,
+            instability: 0.0,
+            score_drop: 0.0,
+            stable_iterations: 0,
+        }
+    }
+
+    /// Starts the clock again, e.g. while pondering, when the time spent isn't the engine's
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// The search has to be over by then, whatever it is doing
+    pub fn hard_deadline(&self) -> Instant {
+        self.start + self.maximum
+    }
+
+    /// Time after which no new iteration is started. It grows while the best move keeps
+    /// changing or the score drops, and shrinks once the best move has stayed the same for a
+    /// while. A fixed move time stays as it is.
+    pub fn soft_limit(&self) -> Duration {
+        if self.fixed {
+            return self.optimum;
+        }
+        let stability = if self.stable_iterations >= STABLE_ITERATIONS {
+            0.6
+        } else {
+            1.0
+        };
+        let scale =
+            ((1.0 + self.instability) * (1.0 + self.score_drop) * stability).clamp(0.4, 3.0);
+        self.optimum.mul_f64(scale).min(self.maximum)
+    }
+
+    /// Records the result of a completed iteration
+    pub fn iteration_done(&mut self, best_move: Move, best_eval: Score) {
+        // old changes count for less with every iteration
+        self.instability *= 0.5;
+        match self.best_move {
+            Some(last) if last != best_move => {
+                self.instability += 1.0;
+                self.stable_iterations = 0;
+            }
+            Some(_) => self.stable_iterations += 1,
+            None => {}
+        }
+        // losing a pawn or more doubles the time, winning some doesn't need any extra
+        let drop = self.best_eval.map_or(0, |last| {
+            let drop = last.value().saturating_sub(best_eval.value());
+            eval_to_centipawns(drop.min(centipawns_to_eval(100)))
+        });
+        self.score_drop = (drop as f64 / 100.0).max(0.0);
+        self.best_move = Some(best_move);
+        self.best_eval = Some(best_eval);
+    }
+
+    pub fn should_stop(&self) -> bool {
+        self.elapsed() >= self.soft_limit()
+    }
+}
@@ -1,16 +1,30 @@
-use std::collections::{HashMap, hash_map::Entry};
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
 
-use nohash_hasher::BuildNoHashHasher;
+use crate::{
+    engine::{ZobristHash, score::Score},
+    moving::{Move, MoveType},
+    piece::PieceType,
+};
 
-use crate::engine::ZobristHash;
-
-// pub type TranspositionTable = HashMap<ZobristHash, TTableEntry, BuildNoHashHasher<ZobristHash>>;
+/// Entries sharing a bucket: the first one keeps the deepest search, the second one takes
+/// whatever doesn't go there
+const BUCKET_SIZE: usize = 2;
+/// Buckets looked at to estimate how full the table is
+const HASHFULL_SAMPLE: usize = 500;
+/// Generations wrap around within the bits they are packed into
+const GENERATION_MASK: u8 = 0x3f;
 
 #[derive(Clone, Copy, Debug)]
 pub struct TTableEntry {
     pub depth: i32,
-    pub score: i64,
+    /// mates counted from the position itself, see `Score::to_tt`
+    pub score: Score,
     pub node_type: NodeType,
+    /// the move that was best or caused the cutoff, searched first when the position comes up
+    /// again
+    pub best_move: Option<PackedMove>,
+    /// the search the entry was written in
+    generation: u8,
 }
 #[derive(Clone, Copy, Debug)]
 pub enum NodeType {
@@ -19,45 +33,134 @@ pub enum NodeType {
     LowerBound,
 }
 
+/// A move in 16 bits: from and to square and the piece promoted to. Which move it is only
+/// becomes clear together with the moves of the position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PackedMove(u16);
+
+impl PackedMove {
+    pub fn new(mov: &Move) -> Self {
+        let promotion = match mov.move_type {
+            MoveType::Promotion(PieceType::Knight) => 1,
+            MoveType::Promotion(PieceType::Bishop) => 2,
+            MoveType::Promotion(PieceType::Rook) => 3,
+            MoveType::Promotion(_) => 4,
+            _ => 0,
+        };
+        Self(*mov.from as u16 | (*mov.to as u16) << 6 | promotion << 12)
+    }
+
+    /// The move among `moves` this stands for, `None` if a collision left a move that isn't
+    /// legal here
+    pub fn find_in(self, moves: &[Move]) -> Option<Move> {
+        moves.iter().copied().find(|mov| Self::new(mov) == self)
+    }
+}
+
+/// A fixed-size hash table shared by every search thread without locks. Each entry is two
+/// words, the key is stored XORed with the data, so an entry torn by two threads writing at
+/// once doesn't verify and reads as missing.
 pub struct TranspositionTable {
-    table: HashMap<ZobristHash, TTableEntry, BuildNoHashHasher<ZobristHash>>,
+    buckets: Box<[[Slot; BUCKET_SIZE]]>,
+    generation: AtomicU8,
+}
+
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
 }
+
 impl TranspositionTable {
-    pub fn new() -> Self {
+    /// A table taking up `size_mb` megabytes, at least one bucket
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024 / size_of::<[Slot; BUCKET_SIZE]>()).max(1);
         Self {
-            table: HashMap::with_hasher(BuildNoHashHasher::default()),
+            buckets: (0..len).map(|_| Default::default()).collect(),
+            generation: AtomicU8::new(0),
         }
     }
 
-    pub fn insert(&mut self, zobrist: ZobristHash, score: i64, depth: i32, node_type: NodeType) {
-        self.table.insert(
-            zobrist,
-            TTableEntry {
-                score,
-                depth,
-                node_type,
-            },
-        );
+    /// Starts a new search, entries of earlier ones get replaced first
+    pub fn new_search(&self) {
+        let generation = (self.generation() + 1) & GENERATION_MASK;
+        self.generation.store(generation, Ordering::Relaxed);
     }
 
-    pub fn get(&self, zobrist: ZobristHash, depth: i32, alpha: i64, beta: i64) -> Option<i64> {
-        let entry = self.table.get(&zobrist)?;
+    /// Stores what the search found about the position `ply` plies from the root
+    pub fn insert(
+        &self,
+        zobrist: ZobristHash,
+        ply: usize,
+        score: Score,
+        depth: i32,
+        node_type: NodeType,
+        best_move: Option<Move>,
+    ) {
+        let bucket = self.bucket(zobrist);
+        let [deepest, latest] = bucket.each_ref().map(|slot| slot.load(zobrist));
+        // a node where every move failed low has no best move, the last one known is still
+        // the best guess
+        let best_move = best_move
+            .map(|mov| PackedMove::new(&mov))
+            .or_else(|| deepest.or(latest)?.best_move);
+        let entry = TTableEntry {
+            depth,
+            score: score.to_tt(ply),
+            node_type,
+            best_move,
+            generation: self.generation(),
+        };
+        let replace_deepest = match bucket[0].load_any() {
+            None => true,
+            Some((key, stored)) => {
+                key == zobrist || stored.generation != entry.generation || depth >= stored.depth
+            }
+        };
+        bucket[usize::from(!replace_deepest)].store(zobrist, &entry);
+    }
+
+    pub fn best_move(&self, zobrist: ZobristHash) -> Option<PackedMove> {
+        self.stored(zobrist)?.best_move
+    }
+
+    /// Whatever is stored about the position `ply` plies from the root, whether or not it is
+    /// deep enough to be used
+    pub fn entry(&self, zobrist: ZobristHash, ply: usize) -> Option<TTableEntry> {
+        let entry = self.stored(zobrist)?;
+        Some(TTableEntry {
+            score: entry.score.from_tt(ply),
+            ..entry
+        })
+    }
+
+    /// The stored score of the position `ply` plies from the root, if it was searched at least
+    /// `depth` deep and settles the window between `alpha` and `beta`
+    pub fn get(
+        &self,
+        zobrist: ZobristHash,
+        ply: usize,
+        depth: i32,
+        alpha: Score,
+        beta: Score,
+    ) -> Option<Score> {
+        let entry = self.entry(zobrist, ply)?;
         if entry.depth >= depth {
             match entry {
-                &TTableEntry {
-                    depth: _,
+                TTableEntry {
                     score,
                     node_type: NodeType::PV,
+                    ..
                 } => Some(score),
-                &TTableEntry {
-                    depth: _,
+                TTableEntry {
                     score,
                     node_type: NodeType::LowerBound,
+                    ..
                 } if score >= beta => Some(score),
-                &TTableEntry {
-                    depth: _,
+                TTableEntry {
                     score,
                     node_type: NodeType::UpperBound,
+                    ..
                 } if score <= alpha => Some(score),
                 _ => None,
             }
@@ -66,7 +169,105 @@ impl TranspositionTable {
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.table.len()
+    /// Permille of the table filled by the current search, estimated from its first buckets
+    pub fn hashfull(&self) -> u32 {
+        let generation = self.generation();
+        let sample = &self.buckets[..self.buckets.len().min(HASHFULL_SAMPLE)];
+        let used = sample
+            .iter()
+            .flatten()
+            .filter_map(Slot::load_any)
+            .filter(|(_, entry)| entry.generation == generation)
+            .count();
+        (used * 1000 / (sample.len() * BUCKET_SIZE)) as u32
     }
+
+    pub fn clear(&self) {
+        for slot in self.buckets.iter().flatten() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// The entry as stored, with mates counted from the position
+    fn stored(&self, zobrist: ZobristHash) -> Option<TTableEntry> {
+        self.bucket(zobrist)
+            .iter()
+            .find_map(|slot| slot.load(zobrist))
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    fn bucket(&self, zobrist: ZobristHash) -> &[Slot; BUCKET_SIZE] {
+        // the high half of the product maps the hash evenly onto any number of buckets
+        let index = (zobrist as u128 * self.buckets.len() as u128) >> 64;
+        &self.buckets[index as usize]
+    }
+}
+
+impl Slot {
+    /// The entry if it is about the position `zobrist`
+    fn load(&self, zobrist: ZobristHash) -> Option<TTableEntry> {
+        self.load_any()
+            .filter(|(key, _)| *key == zobrist)
+            .map(|(_, entry)| entry)
+    }
+
+    /// The entry with the key it was stored under, `None` while the slot is empty
+    fn load_any(&self) -> Option<(ZobristHash, TTableEntry)> {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ data;
+        Some((key, unpack(data)?))
+    }
+
+    fn store(&self, zobrist: ZobristHash, entry: &TTableEntry) {
+        let data = pack(entry);
+        self.key.store(zobrist ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// data word: score in the low 32 bits, then depth, node type, generation and best move
+fn pack(entry: &TTableEntry) -> u64 {
+    let node_type = match entry.node_type {
+        NodeType::PV => 1,
+        NodeType::UpperBound => 2,
+        NodeType::LowerBound => 3,
+    };
+    let depth = entry.depth.clamp(i8::MIN as i32, i8::MAX as i32) as i8;
+    pack_score(entry.score) as u32 as u64
+        | (depth as u8 as u64) << 32
+        | node_type << 40
+        | ((entry.generation & GENERATION_MASK) as u64) << 42
+        | (entry.best_move.map_or(0, |mov| mov.0) as u64) << 48
+}
+
+fn unpack(data: u64) -> Option<TTableEntry> {
+    let node_type = match (data >> 40) & 0b11 {
+        1 => NodeType::PV,
+        2 => NodeType::UpperBound,
+        3 => NodeType::LowerBound,
+        // never written
+        _ => return None,
+    };
+    let best_move = (data >> 48) as u16;
+    Some(TTableEntry {
+        score: unpack_score(data as u32 as i32),
+        depth: (data >> 32) as u8 as i8 as i32,
+        node_type,
+        best_move: (best_move != 0).then_some(PackedMove(best_move)),
+        generation: (data >> 42) as u8 & GENERATION_MASK,
+    })
+}
+
+/// Scores fit into 32 bits, mates included
+fn pack_score(score: Score) -> i32 {
+    score.value().clamp(-(i32::MAX as i64), i32::MAX as i64) as i32
+}
+
+fn unpack_score(score: i32) -> Score {
+    Score::new(score as i64)
 }
@@ -6,7 +6,7 @@ use std::{
 
 use crate::{
     board::SearchBoard,
-    engine::{evaluate::Outcome, play::Game},
+    engine::{evaluate::Outcome, play::Game, search_limits::SearchLimits},
     ffi::struct_reprs::{FFIMove, SimplePieceRepr},
     position::Position,
 };
@@ -126,7 +126,9 @@ pub extern "C" fn gm_searchboard(game: Option<&Game>) -> *mut SearchBoard {
 pub extern "C" fn gm_best_move(game: Option<&Game>, depth: i32) -> FFIMove {
     let game = game.expect("Game should not be null");
 
-    let best_move = game.find_best_move(depth).map(|i| From::from(i));
+    let best_move = game
+        .find_best_move(SearchLimits::depth(depth))
+        .map(|i| From::from(i));
 
     best_move.expect("Don't call Game::best_move() if the outcome of the game is not Ongoing")
 }
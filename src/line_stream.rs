@@ -0,0 +1,217 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Stdout, Write, stdin, stdout},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, channel},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::engine::stop_token::StopToken;
+
+/// What sets a line based protocol like UCI or CECP apart: how its lines parse, how its replies
+/// are written and what its commands do to a running search. An instance lives on the listener
+/// thread, so it can remember what it saw.
+pub trait Protocol: Default + Send + 'static {
+    type Command: Send + 'static;
+    type ParseError: Send + 'static;
+    type Reply;
+
+    /// `None` for lines that are skipped without a word, like empty ones
+    fn parse(line: &str) -> Option<Result<Self::Command, Self::ParseError>>;
+
+    /// The answer to a line that didn't parse
+    fn error_reply(err: Self::ParseError) -> Self::Reply;
+
+    fn write_reply(writer: &mut impl Write, reply: &Self::Reply) -> io::Result<()>;
+
+    /// Whether the listener stops reading after `command`
+    fn is_quit(command: &Self::Command) -> bool;
+
+    /// What `command` does to the running search
+    fn interrupt(&mut self, command: &Self::Command) -> Interrupt;
+
+    /// What the end of input does to the running search
+    fn end_of_input(&mut self) -> Interrupt;
+
+    /// The reply to `command` if it has to be answered at once while the session is searching,
+    /// instead of being queued
+    fn busy_reply(command: &Self::Command) -> Option<Self::Reply>;
+}
+
+/// How a command reaches the running search, which can't read commands while it runs
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interrupt {
+    None,
+    Stop,
+    PonderHit,
+}
+
+/// Both ends of a conversation in protocol `P`. Commands are read and parsed on a listener
+/// thread, replies go to `writer`. Any reader and writer pair works: stdin/stdout, pipes,
+/// sockets or in memory buffers for tests. Lines that don't parse are answered with the
+/// protocol's error reply.
+pub struct LineStream<P: Protocol, W: Write = Stdout> {
+    inner: Receiver<Result<P::Command, P::ParseError>>,
+    // shared with the listener, which answers some commands itself during a search
+    writer: Arc<Mutex<W>>,
+    /// set by the session while it searches. The listener holds the lock from looking at it
+    /// until the command is queued, so a command is either answered by the listener or found
+    /// in the queue by a session that starts searching.
+    searching: Arc<Mutex<bool>>,
+    listening: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+    // the session is busy while searching, so commands have to reach the search from the
+    // listener
+    search_stop: StopToken,
+}
+
+impl<P: Protocol> LineStream<P, Stdout> {
+    pub fn stdio() -> Self {
+        Self::new(stdin(), stdout())
+    }
+}
+
+impl<P: Protocol, W: Write + Send + 'static> LineStream<P, W> {
+    pub fn new<R: Read + Send + 'static>(reader: R, writer: W) -> Self {
+        let (sender, receiver) = channel();
+        let writer = Arc::new(Mutex::new(writer));
+        let searching = Arc::new(Mutex::new(false));
+        let listening = Arc::new(AtomicBool::new(true));
+        let search_stop = StopToken::new();
+
+        let listener = {
+            let writer = Arc::clone(&writer);
+            let searching = Arc::clone(&searching);
+            let listening = Arc::clone(&listening);
+            let search_stop = search_stop.clone();
+            thread::spawn(move || {
+                let mut protocol = P::default();
+                let mut reader = BufReader::new(reader);
+                loop {
+                    let mut buf = String::new();
+                    if !matches!(reader.read_line(&mut buf), Ok(1..)) {
+                        // EOF means the other side went away, closing the channel ends the
+                        // session once everything queued is handled. Sessions check
+                        // `is_listening` when they reset the token for a search, so it is
+                        // cleared before the search is interrupted.
+                        listening.store(false, Ordering::Relaxed);
+                        apply(protocol.end_of_input(), &search_stop);
+                        break;
+                    }
+                    let Some(command) = P::parse(&buf) else {
+                        continue;
+                    };
+                    let (interrupt, is_quit) = match &command {
+                        Ok(command) => (protocol.interrupt(command), P::is_quit(command)),
+                        // reported by `read`, in order with the commands around it
+                        Err(_) => (Interrupt::None, false),
+                    };
+                    let searching = searching.lock().unwrap();
+                    if let (true, Ok(command)) = (*searching, &command)
+                        && let Some(reply) = P::busy_reply(command)
+                    {
+                        write_reply::<P, W>(&writer, &reply);
+                        continue;
+                    }
+                    if sender.send(command).is_err() {
+                        break;
+                    }
+                    drop(searching);
+                    // queued first, so a session that resets the token for a new search still
+                    // finds the command that came before it
+                    apply(interrupt, &search_stop);
+                    if is_quit {
+                        break;
+                    }
+                }
+                listening.store(false, Ordering::Relaxed);
+            })
+        };
+
+        Self {
+            inner: receiver,
+            writer,
+            searching,
+            listening,
+            listener: Some(listener),
+            search_stop,
+        }
+    }
+
+    pub fn send(&self, reply: P::Reply) {
+        write_reply::<P, W>(&self.writer, &reply);
+    }
+
+    /// Tells the listener whether the session is searching. Commands queued before the search
+    /// starts still have to be looked at by the session.
+    pub fn set_searching(&self, searching: bool) {
+        *self.searching.lock().unwrap() = searching;
+    }
+
+    pub fn try_read(&self) -> Option<P::Command> {
+        loop {
+            match self.inner.try_recv().ok()? {
+                Ok(command) => return Some(command),
+                Err(err) => self.send(P::error_reply(err)),
+            }
+        }
+    }
+
+    /// Blocks until the next command arrives. Returns `None` once the listener has exited.
+    pub fn read(&self) -> Option<P::Command> {
+        loop {
+            match self.inner.recv().ok()? {
+                Ok(command) => return Some(command),
+                Err(err) => self.send(P::error_reply(err)),
+            }
+        }
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.listening.load(Ordering::Relaxed)
+    }
+
+    /// Interrupted by the listener as commands arrive, see `Protocol::interrupt`
+    pub fn stop_token(&self) -> StopToken {
+        self.search_stop.clone()
+    }
+
+    /// Waits for the listener thread to exit. Only call it once the protocol's quit came
+    /// through (or `read` returned `None`), before that the listener is still blocked on input.
+    pub fn shutdown(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.join().ok();
+        }
+    }
+
+    /// Stops listening and hands back the writer, mostly to inspect what was sent
+    pub fn into_writer(mut self) -> W {
+        self.shutdown();
+        let writer = Arc::try_unwrap(self.writer).unwrap_or_else(|_| {
+            panic!("the listener has exited, nothing else holds the writer");
+        });
+        writer.into_inner().unwrap()
+    }
+}
+
+impl<P: Protocol> Default for LineStream<P, Stdout> {
+    fn default() -> Self {
+        Self::stdio()
+    }
+}
+
+fn write_reply<P: Protocol, W: Write>(writer: &Mutex<W>, reply: &P::Reply) {
+    let mut writer = writer.lock().unwrap();
+    P::write_reply(&mut *writer, reply).ok();
+    writer.flush().ok();
+}
+
+fn apply(interrupt: Interrupt, search: &StopToken) {
+    match interrupt {
+        Interrupt::None => {}
+        Interrupt::Stop => search.stop(),
+        Interrupt::PonderHit => search.ponderhit(),
+    }
+}
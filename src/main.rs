@@ -1,8 +1,10 @@
 pub mod board;
 pub mod board_repr;
+pub mod cecp;
 #[allow(dead_code)]
 pub mod engine;
 pub mod hashers;
+pub mod line_stream;
 pub mod magic_bitboards;
 pub mod moving;
 #[allow(dead_code)]
@@ -15,6 +17,7 @@ pub mod position;
 pub mod search;
 pub mod search_data;
 pub mod search_masks;
+pub mod see;
 pub mod uci;
 pub mod util;
 pub mod zobrist;
@@ -22,8 +25,17 @@ pub mod zobrist;
 #[cfg(feature = "ffi")]
 pub mod ffi;
 
+use crate::cecp::{session::CecpSession, stream::CecpStream};
 use crate::engine::bot::Bot;
+use crate::engine::search_limits::SearchLimits;
+use crate::uci::{
+    client::UciEngineProcess,
+    match_runner::{MatchConfig, MatchRunner},
+    session::UciSession,
+    stream::UciStream,
+};
 pub use crate::util::pseudo_moving;
+use std::io::{Cursor, Read, stdin, stdout};
 
 fn main() {
     #[cfg(feature = "perft")]
@@ -34,29 +46,88 @@ fn main() {
     }
     #[cfg(not(feature = "perft"))]
     {
-        let mut game = Bot::default();
-        // game.make_move(&Move::from_string(&game.get_board().state, "a2 a3").unwrap());
-        // game.make_move(&Move::from_string(&game.get_board().state, "a7 a6").unwrap());
-        game.autoplay(6);
+        let args: Vec<String> = std::env::args().collect();
+        match args.get(1).map(|arg| arg.as_str()) {
+            Some("autoplay") => {
+                let mut game = Bot::default();
+                // game.make_move(&Move::from_string(&game.get_board().state, "a2 a3").unwrap());
+                // game.make_move(&Move::from_string(&game.get_board().state, "a7 a6").unwrap());
+                game.autoplay(SearchLimits::depth(6));
+            }
+            // match <engine> [games] [960]: plays against an external engine, the PGN goes to stdout
+            Some("match") => {
+                let engine = args.get(2).expect("usage: match <engine> [games] [960]");
+                let config = MatchConfig {
+                    games: args.get(3).map_or(2, |games| games.parse().expect("games")),
+                    chess960: args.get(4).is_some_and(|variant| variant == "960"),
+                    ..Default::default()
+                };
+                let engine = UciEngineProcess::spawn(engine, &[]).expect("engine should start");
+                let score = MatchRunner::new(engine, config)
+                    .run(&mut std::io::stdout())
+                    .expect("match failed");
+                eprintln!("+{} -{} ={}", score.wins, score.losses, score.draws);
+            }
+            _ => {
+                // XBoard announces itself with `xboard`, anything else is taken for UCI
+                let mut first_line = String::new();
+                stdin().read_line(&mut first_line).ok();
+                let input = Cursor::new(first_line.clone()).chain(stdin());
+                if first_line.trim() == "xboard" {
+                    CecpSession::new(CecpStream::new(input, stdout())).run();
+                } else {
+                    UciSession::new(UciStream::new(input, stdout())).run();
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        engine::{bot::Bot, evaluate::Outcome},
+        board::SearchBoard,
+        cecp::{session::CecpSession, stream::CecpStream},
+        engine::{
+            bot::Bot,
+            config::{Pruning, SearchConfig},
+            evaluate::{Outcome, centipawns_to_eval, is_passed_pawn},
+            move_ordering::{MoveOrdering, is_quiet},
+            score::Score,
+            search_info::{InfoScore, SearchInfo},
+            search_limits::SearchLimits,
+            time_manager::TimeManager,
+            transposition_table::{NodeType, TranspositionTable},
+        },
         moving::{Move, MoveType},
+        piece::Side,
         position::Position,
+        uci::{
+            client::{EngineInfo, EngineReply, UciEngineProcess},
+            command::{GoParams, UciCommand, UciParseError, UciPosition},
+            match_runner::{MatchConfig, MatchRunner, MatchScore},
+            options::{UciOptionKind, UciOptions},
+            session::UciSession,
+            stream::UciStream,
+        },
+    };
+    use std::{
+        io::{self, Read, Write},
+        sync::{
+            Arc, Mutex,
+            mpsc::{self, Receiver},
+        },
+        thread,
+        time::{Duration, Instant},
     };
 
     #[test]
     fn white_mate_in_one() {
         let mut game = Bot::from_fen("8/8/8/3K4/7k/8/6R1/5R2 w - - 0 1");
-        let moves: Vec<_> = game.find_best_moves(1).unwrap();
-        for (mov, eval) in &moves {
-            println!("{}: {}", eval, mov);
-        }
-        let moves: Vec<_> = moves.into_iter().map(|e| e.0).collect();
+        let moves = game
+            .find_best_moves(SearchLimits::depth(1))
+            .unwrap()
+            .best_moves;
 
         let stockfish_move = Move::new(
             Position::from_str("f1").unwrap(),
@@ -65,18 +136,17 @@ mod tests {
             None,
         );
         assert!(moves.contains(&stockfish_move));
-        let outcome = game.make_best_move(1);
+        let outcome = game.make_best_move(SearchLimits::depth(1));
         assert_eq!(outcome, Outcome::WhiteWon);
     }
 
     #[test]
     fn black_mate_in_one() {
         let mut game = Bot::from_fen("8/8/8/3k4/7K/8/6r1/5r2 b - - 0 1");
-        let moves: Vec<_> = game.find_best_moves(1).unwrap();
-        for (mov, eval) in &moves {
-            println!("{}: {}", eval, mov);
-        }
-        let moves: Vec<_> = moves.into_iter().map(|e| e.0).collect();
+        let moves = game
+            .find_best_moves(SearchLimits::depth(1))
+            .unwrap()
+            .best_moves;
 
         let stockfish_move = Move::new(
             Position::from_str("f1").unwrap(),
@@ -86,7 +156,1015 @@ mod tests {
         );
         assert!(moves.contains(&stockfish_move));
         println!("{}", moves[0]);
-        let outcome = game.make_best_move(1);
+        let outcome = game.make_best_move(SearchLimits::depth(1));
         assert_eq!(outcome, Outcome::BlackWon);
     }
+
+    #[test]
+    fn deepening_reports_every_depth() {
+        let mut game =
+            Bot::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let mut depths = Vec::new();
+        let result = game
+            .find_best_moves_reporting(SearchLimits::depth(4), |info| depths.extend(info.depth))
+            .unwrap();
+        assert_eq!(depths, [1, 2, 3, 4]);
+        assert_eq!(result.depth, 4);
+        assert!(game.legal_moves().contains(&result.best_move));
+    }
+
+    #[test]
+    fn principal_variation_is_playable() {
+        let mut game = Bot::from_fen("8/8/8/3K4/7k/8/6R1/5R2 w - - 0 1");
+        let result = game.find_best_moves(SearchLimits::depth(3)).unwrap();
+        assert_eq!(result.pv, [result.best_move]);
+        assert_eq!(game.make_move(&result.pv[0]), Some(Outcome::WhiteWon));
+
+        let mut game =
+            Bot::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let result = game.find_best_moves(SearchLimits::depth(4)).unwrap();
+        assert_eq!(result.pv[0], result.best_move);
+        assert!(result.pv.len() > 1 && result.ponder_move() == Some(result.pv[1]));
+        assert!(result.nodes > 0);
+        for mov in &result.pv {
+            assert!(game.make_move(mov).is_some(), "{} is illegal", mov);
+        }
+
+        let output = run_uci("position startpos\ngo depth 3\n");
+        let bestmove = output.last().unwrap();
+        assert!(bestmove.starts_with("bestmove ") && bestmove.contains(" ponder "));
+    }
+
+    #[test]
+    fn move_ordering_priorities() {
+        let game =
+            Bot::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let moves = game.legal_moves();
+        let (quiets, captures): (Vec<_>, Vec<_>) = moves.iter().partition(|mov| is_quiet(mov));
+        let mut ordering = MoveOrdering::new();
+        let score = |ordering: &MoveOrdering, mov: &Move| {
+            ordering.score(game.get_board(), mov, 3, Some(quiets[3]), None)
+        };
+        let (captures, losing): (Vec<_>, Vec<_>) = captures
+            .into_iter()
+            .partition(|mov| game.get_board().see_ge(mov, 0));
+
+        // the quiet move that cut off turns into a killer, the one tried before it loses history
+        ordering.update(quiets[0], Side::White, 3, 4, None, &[quiets[1]]);
+        assert!(score(&ordering, &quiets[0]) > score(&ordering, &quiets[2]));
+        assert!(score(&ordering, &quiets[1]) < score(&ordering, &quiets[2]));
+        for capture in &captures {
+            assert!(score(&ordering, capture) > score(&ordering, &quiets[0]));
+            assert!(score(&ordering, capture) < score(&ordering, &quiets[3]));
+        }
+        for capture in &losing {
+            assert!(score(&ordering, capture) < score(&ordering, &quiets[1]));
+        }
+        // the queen taking on h3 comes last of all: the rook on h8 wins the exchange, and among
+        // the losing captures it has the least valuable victim for the most valuable attacker
+        let qxh3 = *moves
+            .iter()
+            .max_by_key(|mov| -score(&ordering, mov))
+            .unwrap();
+        assert_eq!(
+            (qxh3.from, qxh3.to),
+            (
+                Position::from_str("f3").unwrap(),
+                Position::from_str("h3").unwrap()
+            )
+        );
+
+        // killers don't survive into the next search, the history does
+        let killer = score(&ordering, &quiets[0]);
+        ordering.age();
+        assert!(score(&ordering, &quiets[0]) < killer);
+        assert!(score(&ordering, &quiets[0]) > score(&ordering, &quiets[2]));
+    }
+
+    #[test]
+    fn static_exchange_evaluation() {
+        let see = |fen: &str, mov: &str| {
+            let game = Bot::from_fen(fen);
+            let mov = game.parse_move(mov).unwrap();
+            game.get_board().see(&mov)
+        };
+        // a free pawn, and one the queen loses itself for
+        assert_eq!(see("4k3/8/8/4p3/8/8/8/4QK2 w - - 0 1", "e1e5"), 50);
+        assert_eq!(see("4k3/8/5p2/4p3/8/8/8/4QK2 w - - 0 1", "e1e5"), -400);
+        // the second rook joins the exchange through the first one
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/5K2 w - - 0 1", "e2e5"), -200);
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/4RK2 w - - 0 1", "e2e5"), 50);
+        // the king takes back, unless the piece is defended
+        assert_eq!(see("8/8/8/3k4/4p3/8/8/4RK2 w - - 0 1", "e1e4"), -200);
+        assert_eq!(see("8/8/8/3k4/4p3/8/6B1/4RK2 w - - 0 1", "e1e4"), 50);
+
+        let game = Bot::from_fen("4k3/8/5p2/4p3/8/8/8/4QK2 w - - 0 1");
+        let qxe5 = game.parse_move("e1e5").unwrap();
+        assert!(game.get_board().see_ge(&qxe5, -400));
+        assert!(!game.get_board().see_ge(&qxe5, 0));
+    }
+
+    #[test]
+    fn multi_pv() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut game = Bot::from_fen(fen);
+        game.set_config(SearchConfig {
+            multi_pv: 3,
+            ..Default::default()
+        });
+        let result = game.find_best_moves(SearchLimits::depth(3)).unwrap();
+        assert_eq!(result.lines.len(), 3);
+        assert_eq!(result.lines[0].pv, result.pv);
+        assert_eq!(result.lines[0].score, result.score);
+        assert!(result.lines.is_sorted_by_key(|line| -line.score));
+        let first_moves: Vec<_> = result.lines.iter().map(|line| line.pv[0]).collect();
+        assert!(first_moves[1..].iter().all(|mov| *mov != first_moves[0]));
+        assert_ne!(first_moves[1], first_moves[2]);
+
+        // one line without the option
+        game.set_config(SearchConfig::default());
+        assert_eq!(
+            game.find_best_moves(SearchLimits::depth(3))
+                .unwrap()
+                .lines
+                .len(),
+            1
+        );
+
+        let mut session = UciSession::new(UciStream::new(
+            "setoption name MultiPV value 2\nposition startpos\ngo depth 2\n".as_bytes(),
+            Vec::new(),
+        ));
+        session.run();
+        let output = String::from_utf8(session.into_stream().into_writer()).unwrap();
+        assert!(output.contains("info depth 2 seldepth"));
+        assert!(output.lines().any(|line| line.contains(" multipv 2 ")));
+    }
+
+    #[test]
+    fn search_limits() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        // a node limit cuts the search short at the same point every time
+        let search = || {
+            Bot::from_fen(fen)
+                .find_best_moves(SearchLimits::nodes(20_000))
+                .unwrap()
+        };
+        let (first, second) = (search(), search());
+        assert_eq!(first, second);
+        assert!(first.nodes <= 20_000 && first.depth > 0);
+
+        // only the allowed root moves are searched
+        let mut game = Bot::default();
+        let a3 = game.parse_move("a2a3").unwrap();
+        let h3 = game.parse_move("h2h3").unwrap();
+        let result = game
+            .find_best_moves(SearchLimits::depth(3).with_search_moves(vec![a3, h3]))
+            .unwrap();
+        assert!([a3, h3].contains(&result.best_move));
+        let best = game.find_best_move(SearchLimits::depth(3)).unwrap().0;
+        let result = game
+            .find_best_moves(SearchLimits::depth(3).with_excluded_moves(vec![best]))
+            .unwrap();
+        assert_ne!(result.best_move, best);
+        // nothing to search if the limits leave no root move
+        let every_move = game.legal_moves().to_vec();
+        let limits = SearchLimits::depth(2).with_excluded_moves(every_move);
+        assert!(game.find_best_moves(limits).is_none());
+        // a move of another position, not legal here
+        let king_move = Bot::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1")
+            .parse_move("e8d8")
+            .unwrap();
+        let limits = SearchLimits::depth(2).with_search_moves(vec![king_move]);
+        assert!(game.find_best_moves(limits).is_none());
+        let limits = SearchLimits::depth(2).with_search_moves(vec![king_move]);
+        assert_eq!(game.make_best_move(limits), Outcome::Ongoing);
+        assert_eq!(game.outcome(), Outcome::Ongoing);
+
+        // a mate search stops once it found a mate that fast
+        let mut game = Bot::from_fen("5r1k/6pp/8/6N1/8/1Q6/8/6K1 w - - 0 1");
+        let result = game.find_best_moves(SearchLimits::mate(4)).unwrap();
+        assert_eq!(InfoScore::from(result.score), InfoScore::Mate(4));
+        assert!(result.depth <= 7);
+
+        let output = run_uci("position startpos\ngo nodes 3000 searchmoves g1f3\n");
+        assert!(output.last().unwrap().starts_with("bestmove g1f3"));
+        // moves that aren't legal are left out
+        let output = run_uci("position startpos\ngo nodes 3000 searchmoves e2e5 xyz g1f3\n");
+        assert!(output.last().unwrap().starts_with("bestmove g1f3"));
+    }
+
+    #[test]
+    fn lazy_smp() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        // a single thread searches the same tree every time
+        let search = || {
+            Bot::from_fen(fen)
+                .find_best_moves(SearchLimits::depth(4))
+                .unwrap()
+        };
+        let (first, second) = (search(), search());
+        assert_eq!(first, second);
+
+        // the helpers' nodes are counted, the main thread still decides
+        let mut game = Bot::from_fen("8/8/8/3K4/7k/8/6R1/5R2 w - - 0 1");
+        game.set_config(SearchConfig {
+            threads: 4,
+            ..Default::default()
+        });
+        let result = game.find_best_moves(SearchLimits::depth(3)).unwrap();
+        assert_eq!(result.best_move.into_long_algebraic(), "f1h1");
+        assert_eq!(result.score.mate_moves(), Some(1));
+        assert!(result.nodes > 0);
+    }
+
+    #[test]
+    fn quiescence() {
+        // the queen stays out of the pawns' reach, and a quiet reply doesn't make it vanish
+        let mut game = Bot::from_fen("4k3/8/5p2/4p3/8/8/8/4QK2 w - - 0 1");
+        let result = game.find_best_moves(SearchLimits::depth(1)).unwrap();
+        assert_ne!(result.best_move.into_long_algebraic(), "e1e5");
+        assert!(result.score > Score::new(centipawns_to_eval(500)));
+
+        // a check without a capture to answer it isn't a mate, the king just steps aside
+        let mut game = Bot::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let result = game.find_best_moves(SearchLimits::depth(1)).unwrap();
+        assert!(!result.score.is_mate());
+        assert!(result.score > Score::new(centipawns_to_eval(300)));
+    }
+
+    #[test]
+    fn mate_scores() {
+        // the king can only step aside before the rook mates on the first rank
+        let mut game = Bot::from_fen("6k1/8/8/8/8/r7/1r6/6K1 w - - 0 1");
+        let result = game.find_best_moves(SearchLimits::depth(4)).unwrap();
+        assert_eq!(result.score, Score::mated_in(2));
+        assert_eq!(result.score.mate_moves(), Some(-1));
+        assert_eq!(result.score.to_string(), "mate -1");
+
+        // nearer mates are better, and every mate is beyond every evaluation
+        assert!(Score::mate_in(1) > Score::mate_in(3));
+        assert!(Score::mated_in(2) < Score::mated_in(4));
+        assert!(Score::mated_in(100) < Score::new(-100_000));
+        assert_eq!(Score::mate_in(5).mate_moves(), Some(3));
+        assert_eq!(Score::new(-100_000).mate_moves(), None);
+    }
+
+    #[test]
+    fn stalemate_is_a_draw() {
+        let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        assert_eq!(Bot::from_fen(stalemate).static_evaluate(), Score::ZERO);
+
+        // the evaluation agrees with the search, which scores draws with the contempt
+        let mut game = Bot::from_fen(stalemate);
+        game.set_config(SearchConfig {
+            contempt: 20,
+            ..Default::default()
+        });
+        assert_eq!(game.static_evaluate(), Score::new(-centipawns_to_eval(20)));
+    }
+
+    #[test]
+    fn transposition_table_replacement() {
+        let game = Bot::default();
+        let moves = game.legal_moves();
+        let cp = Score::new;
+        // a single bucket
+        let table = TranspositionTable::new(0);
+        // mated 2 plies below a node 1 ply from the root, the same position later comes up at
+        // ply 4
+        table.insert(1, 1, Score::mated_in(3), 5, NodeType::PV, Some(moves[0]));
+        let entry = table.entry(1, 4).unwrap();
+        assert_eq!(entry.score, Score::mated_in(6));
+        assert_eq!(entry.best_move.unwrap().find_in(&moves), Some(moves[0]));
+        assert_eq!(table.get(1, 1, 5, cp(0), cp(1)), Some(Score::mated_in(3)));
+
+        // shallower entries take turns in the second slot, a deeper one takes the first
+        table.insert(2, 0, cp(20), 2, NodeType::LowerBound, None);
+        table.insert(3, 0, cp(-20), 1, NodeType::UpperBound, Some(moves[1]));
+        assert!(table.entry(1, 0).is_some() && table.entry(2, 0).is_none());
+        assert_eq!(table.get(3, 0, 1, cp(-10), cp(10)), Some(cp(-20)));
+        assert_eq!(table.get(3, 0, 1, cp(-30), cp(10)), None);
+        table.insert(4, 0, cp(0), 6, NodeType::PV, None);
+        assert!(table.entry(1, 0).is_none() && table.entry(3, 0).is_some());
+        assert_eq!(table.hashfull(), 1000);
+
+        // entries of an older search make way for any new one
+        table.new_search();
+        assert_eq!(table.hashfull(), 0);
+        table.insert(5, 0, cp(0), 1, NodeType::PV, None);
+        assert!(table.entry(4, 0).is_none() && table.entry(5, 0).is_some());
+        table.clear();
+        assert!(table.entry(3, 0).is_none() && table.entry(5, 0).is_none());
+    }
+
+    #[test]
+    fn selective_search() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut full = Bot::default();
+        full.set_config(SearchConfig {
+            pruning: Pruning::NONE,
+            ..Default::default()
+        });
+        full.load_fen(fen).unwrap();
+        let full = full.find_best_moves(SearchLimits::depth(3)).unwrap();
+        let mut selective = Bot::from_fen(fen);
+        let selective = selective.find_best_moves(SearchLimits::depth(3)).unwrap();
+        assert!(selective.nodes * 2 < full.nodes);
+
+        // the pruning keeps away from mates
+        let mut game = Bot::from_fen("6k1/5ppp/8/8/8/8/q4PPP/1R4K1 w - - 0 1");
+        let result = game.find_best_moves(SearchLimits::depth(5)).unwrap();
+        assert_eq!(result.best_move.into_long_algebraic(), "b1b8");
+        assert!(matches!(InfoScore::from(result.score), InfoScore::Mate(1)));
+
+        // a null move only passes the turn
+        let mut board = SearchBoard::try_from_fen(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        )
+        .unwrap();
+        let (fen, zobrist) = (board.to_fen(), board.zobrist);
+        let unmove = board.make_null();
+        assert_eq!(board.side(), Side::White);
+        assert_eq!(board.en_passant_square, None);
+        assert_ne!(board.zobrist, zobrist);
+        board.unmake_null(unmove);
+        assert_eq!((board.to_fen(), board.zobrist), (fen, zobrist));
+    }
+
+    #[test]
+    fn extensions_follow_forcing_lines() {
+        // the smothered mate: Nf7+ Kg8 Nh6+ Kh8 Qg8+ Rxg8 Nf7#, checks all the way
+        let mut game = Bot::from_fen("5r1k/6pp/8/6N1/8/1Q6/8/6K1 w - - 0 1");
+        let result = game.find_best_moves(SearchLimits::depth(6)).unwrap();
+        let pv: Vec<_> = result
+            .pv
+            .into_iter()
+            .map(Move::into_long_algebraic)
+            .collect();
+        assert_eq!(pv, ["g5f7", "h8g8", "f7h6", "g8h8", "b3g8", "f8g8", "h6f7"]);
+        assert_eq!(InfoScore::from(result.score), InfoScore::Mate(4));
+
+        // pushes of passed pawns to the sixth rank are extended too
+        let passed = |fen, square| {
+            let board = SearchBoard::from_fen(fen);
+            let pawn = Position::from_str(square).unwrap();
+            is_passed_pawn(&board, pawn, board.get_piece_at(pawn).unwrap().side())
+        };
+        assert!(passed("4k3/8/3P4/2p5/8/8/8/4K3 b - - 0 1", "d6"));
+        assert!(!passed("4k3/4p3/3P4/8/8/8/8/4K3 b - - 0 1", "d6"));
+        assert!(passed("4k3/8/8/3P4/8/3p4/8/4K3 w - - 0 1", "d3"));
+        assert!(!passed("4k3/8/8/8/8/3p4/2P5/4K3 w - - 0 1", "d3"));
+    }
+
+    #[test]
+    fn time_manager_limits() {
+        let overhead = Duration::from_millis(10);
+        let time = TimeManager::new(Duration::from_secs(60), Duration::ZERO, None, overhead);
+        let planned = time.soft_limit();
+        assert!(planned > Duration::from_secs(1) && planned < Duration::from_secs(3));
+        assert!(time.hard_deadline() - Instant::now() <= Duration::from_secs(48));
+
+        // a best move that keeps changing takes longer, one that stays put less
+        let moves = Bot::default().legal_moves();
+        let mut unsettled = time.clone();
+        let mut settled = time.clone();
+        for iteration in 0..6 {
+            unsettled.iteration_done(moves[iteration % 2], Score::ZERO);
+            settled.iteration_done(moves[0], Score::ZERO);
+        }
+        assert!(unsettled.soft_limit() > planned);
+        assert!(settled.soft_limit() < planned);
+        // so does a score that drops
+        let mut dropping = time.clone();
+        dropping.iteration_done(moves[0], Score::new(50));
+        dropping.iteration_done(moves[0], Score::new(-50));
+        assert!(dropping.soft_limit() > planned);
+
+        // a fixed move time is used up whatever the search finds
+        let mut fixed = TimeManager::fixed(Duration::from_secs(1), Duration::ZERO);
+        for iteration in 0..6 {
+            fixed.iteration_done(moves[0], Score::new(-50 * iteration));
+            assert_eq!(fixed.soft_limit(), Duration::from_secs(1));
+        }
+
+        // the hard limit holds however deep the search is allowed to go
+        let mut game = Bot::default();
+        let start = Instant::now();
+        let result = game
+            .find_best_moves(SearchLimits::move_time(Duration::from_millis(60)))
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(game.legal_moves().contains(&result.best_move));
+
+        // a clock that already ran out still gets a searched move
+        let time = TimeManager::fixed(Duration::ZERO, Duration::ZERO);
+        let result = Bot::default()
+            .find_best_moves(SearchLimits::default().with_time(time))
+            .unwrap();
+        assert!(result.nodes > 0);
+        let output = run_uci("position startpos\ngo wtime 15 btime 15\n");
+        assert_eq!(output.len(), 1);
+        assert!(output[0].starts_with("bestmove ") && output[0] != "bestmove b1a3");
+    }
+
+    #[test]
+    fn info_lines() {
+        let mut game = Bot::default();
+        let pv = ["e2e4", "e7e5", "g1f3"].map(|mov| {
+            let mov = game.parse_move(mov).unwrap();
+            game.make_move(&mov);
+            mov
+        });
+        let info = SearchInfo {
+            depth: Some(7),
+            seldepth: Some(12),
+            score: Some(InfoScore::Mate(-3)),
+            nodes: Some(123456),
+            nps: Some(987654),
+            time: Some(Duration::from_millis(125)),
+            hashfull: Some(42),
+            pv: pv.to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(
+            UciCommand::Info(info).to_string(),
+            "info depth 7 seldepth 12 score mate -3 nodes 123456 nps 987654 hashfull 42 time 125 \
+             pv e2e4 e7e5 g1f3\n"
+        );
+
+        let info = SearchInfo {
+            depth: Some(2),
+            score: Some(InfoScore::Centipawns(-35)),
+            currmove: Some(pv[2]),
+            currmovenumber: Some(14),
+            ..Default::default()
+        };
+        assert_eq!(
+            UciCommand::Info(info).to_string(),
+            "info depth 2 score cp -35 currmove g1f3 currmovenumber 14\n"
+        );
+    }
+
+    #[test]
+    fn go_params_in_any_order() {
+        let Ok(UciCommand::Go(params)) = UciCommand::parse(
+            "go searchmoves e2e4 d2d4 btime 2000 wtime 3000 movestogo 10 infinite winc 50",
+        ) else {
+            panic!("go command should parse");
+        };
+        assert_eq!(
+            params,
+            GoParams {
+                wtime: Some(Duration::from_millis(3000)),
+                btime: Some(Duration::from_millis(2000)),
+                winc: Some(Duration::from_millis(50)),
+                movestogo: Some(10),
+                infinite: true,
+                searchmoves: vec!["e2e4".to_string(), "d2d4".to_string()],
+                ..Default::default()
+            }
+        );
+        assert!(
+            matches!(UciCommand::parse("go"), Ok(UciCommand::Go(params)) if params == GoParams::default())
+        );
+    }
+
+    #[test]
+    fn stopped_search_still_returns_a_move() {
+        let mut game = Bot::default();
+        game.stop_token().stop();
+        let result = game.find_best_moves(SearchLimits::depth(4)).unwrap();
+        assert_eq!(result.best_moves, [result.best_move]);
+        assert_eq!(result.depth, 0);
+        assert!(game.legal_moves().contains(&result.best_move));
+    }
+
+    #[test]
+    fn setoption_with_multi_word_name() {
+        let Ok(UciCommand::SetOption { name, value }) =
+            UciCommand::parse("setoption name Move Overhead value 30")
+        else {
+            panic!("setoption should parse");
+        };
+        assert_eq!(
+            (name.as_str(), value.as_deref()),
+            ("Move Overhead", Some("30"))
+        );
+
+        let Ok(UciCommand::SetOption { name, value }) =
+            UciCommand::parse("setoption name Clear Hash")
+        else {
+            panic!("setoption should parse");
+        };
+        assert_eq!((name.as_str(), value), ("Clear Hash", None));
+
+        let mut options = UciOptions::new();
+        options.set("move overhead", Some("30")).unwrap();
+        assert!(options.set("Hash", Some("0")).is_err());
+        assert_eq!(
+            options.search_config().move_overhead,
+            Duration::from_millis(30)
+        );
+    }
+
+    #[test]
+    fn malformed_commands_are_errors() {
+        assert_eq!(UciCommand::parse(" \n").err(), Some(UciParseError::Empty));
+        assert!(matches!(
+            UciCommand::parse("joho debug on"),
+            Ok(UciCommand::Debug(true))
+        ));
+        assert!(matches!(
+            UciCommand::parse("hello world"),
+            Err(UciParseError::UnknownCommand(_))
+        ));
+        assert!(matches!(
+            UciCommand::parse("go depth"),
+            Err(UciParseError::Missing { command: "go", .. })
+        ));
+        assert!(matches!(
+            UciCommand::parse("go wtime soon"),
+            Err(UciParseError::Invalid { command: "go", .. })
+        ));
+        let Ok(UciCommand::Go(params)) = UciCommand::parse("go searchmoves e2e4 e9 depth 3") else {
+            panic!("a malformed search move doesn't fail go");
+        };
+        assert_eq!(params.searchmoves, ["e2e4", "e9"]);
+        assert_eq!(params.depth, Some(3));
+        assert!(matches!(
+            UciCommand::parse("setoption value 3"),
+            Err(UciParseError::Missing { .. })
+        ));
+        assert!(matches!(
+            UciCommand::parse("position startpos moves e2e4 e9e5"),
+            Err(UciParseError::Invalid { .. })
+        ));
+
+        let Ok(UciCommand::Position { position, moves }) =
+            UciCommand::parse("position fen 8/8/8/3K4/7k/8/6R1/5R2 w - - 0 1 moves f1h1")
+        else {
+            panic!("position should parse");
+        };
+        assert!(
+            matches!(position, UciPosition::Fen(fen) if fen == "8/8/8/3K4/7k/8/6R1/5R2 w - - 0 1")
+        );
+        assert_eq!(moves, ["f1h1"]);
+    }
+
+    fn run_cecp(script: &'static str) -> Vec<String> {
+        let mut session = CecpSession::new(CecpStream::new(script.as_bytes(), Vec::new()));
+        session.run();
+        let output = session.into_stream().into_writer();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn scripted_xboard_game() {
+        let output = run_cecp(
+            "xboard\nprotover 2\nnew\nforce\nsetboard 8/8/8/3K4/7k/8/6R1/5R2 w - - 0 1\nsd 2\npost\ngo\n",
+        );
+        assert!(output[0].starts_with("feature ping=1 setboard=1"));
+        assert!(output[0].ends_with("done=1"));
+        assert!(output[1].starts_with("1 "));
+        assert_eq!(
+            output[output.len() - 2..],
+            ["move f1h1", "1-0 {White mates}"]
+        );
+
+        // takebacks, illegal moves and unknown commands
+        let output = run_cecp(
+            "new\nforce\nusermove e2e4\ne7e5\nremove\nusermove e7e5\nd2d4\nundo\nfoo\nping 7\n",
+        );
+        assert_eq!(
+            output,
+            [
+                "Illegal move (illegal): e7e5",
+                "Error (unknown command): foo",
+                "pong 7"
+            ]
+        );
+
+        let output = run_cecp("new\nsd 1\nusermove e2e4\n");
+        assert_eq!(output.len(), 1);
+        assert!(output[0].starts_with("move "));
+    }
+
+    fn run_uci(script: &'static str) -> Vec<String> {
+        let mut session = UciSession::new(UciStream::new(script.as_bytes(), Vec::new()));
+        session.run();
+        let output = session.into_stream().into_writer();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with("info") || line.starts_with("info string"))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    /// Input that arrives line by line while the session runs, like from a GUI
+    struct LiveInput {
+        lines: Receiver<String>,
+        pending: Vec<u8>,
+    }
+
+    impl Read for LiveInput {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pending.is_empty() {
+                match self.lines.recv() {
+                    Ok(line) => self.pending = line.into_bytes(),
+                    // the sender is gone, that's the end of input
+                    Err(_) => return Ok(0),
+                }
+            }
+            let len = buf.len().min(self.pending.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    /// Output that can be looked at while the session is still writing to it
+    #[derive(Clone, Default)]
+    struct LiveOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl LiveOutput {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for LiveOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pondering_waits_for_ponderhit() {
+        let (input, lines) = mpsc::channel();
+        let output = LiveOutput::default();
+        let stream = UciStream::new(
+            LiveInput {
+                lines,
+                pending: Vec::new(),
+            },
+            output.clone(),
+        );
+        let session = thread::spawn(move || UciSession::new(stream).run());
+
+        // a clock this short would be used up long ago, but pondering ignores it
+        input
+            .send("position startpos\ngo ponder wtime 1000 btime 1000\n".to_string())
+            .unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let text = output.text();
+        assert!(text.contains("info depth"));
+        assert!(!text.contains("bestmove"));
+
+        // after the ponderhit the clock runs and the search ends on its own
+        input.send("ponderhit\n".to_string()).unwrap();
+        let start = Instant::now();
+        while !output.text().contains("bestmove") {
+            assert!(start.elapsed() < Duration::from_secs(5), "no bestmove");
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(input);
+        session.join().unwrap();
+
+        let text = output.text();
+        let pv: Vec<_> = text
+            .lines()
+            .rfind(|line| line.starts_with("info depth"))
+            .and_then(|line| line.split(" pv ").nth(1))
+            .unwrap()
+            .split(' ')
+            .collect();
+        let bestmove = text.lines().last().unwrap();
+        assert_eq!(bestmove, format!("bestmove {} ponder {}", pv[0], pv[1]));
+    }
+
+    #[test]
+    fn queued_stop_ends_its_own_search() {
+        // the stop belongs to the infinite search, the next `go` must not swallow it
+        let output = run_uci("position startpos\ngo infinite\nstop\ngo depth 2\nisready\n");
+        assert_eq!(output.len(), 3);
+        let bestmoves = output.iter().filter(|line| line.starts_with("bestmove "));
+        assert_eq!(bestmoves.count(), 2);
+        assert!(output.contains(&"readyok".to_string()));
+    }
+
+    #[test]
+    fn isready_during_search() {
+        let (input, lines) = mpsc::channel();
+        let output = LiveOutput::default();
+        let stream = UciStream::new(
+            LiveInput {
+                lines,
+                pending: Vec::new(),
+            },
+            output.clone(),
+        );
+        let session = thread::spawn(move || UciSession::new(stream).run());
+
+        input
+            .send("position startpos\ngo infinite\n".to_string())
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        input.send("isready\n".to_string()).unwrap();
+        let start = Instant::now();
+        while !output.text().contains("readyok") {
+            assert!(start.elapsed() < Duration::from_secs(2), "no readyok");
+            thread::sleep(Duration::from_millis(10));
+        }
+        // answered without stopping the search
+        assert!(!output.text().contains("bestmove"));
+
+        input.send("stop\n".to_string()).unwrap();
+        drop(input);
+        session.join().unwrap();
+        assert!(
+            output
+                .text()
+                .lines()
+                .last()
+                .unwrap()
+                .starts_with("bestmove ")
+        );
+    }
+
+    #[test]
+    fn scripted_uci_conversation() {
+        // an explicit quit would stop the search, the end of input lets it finish
+        let output =
+            run_uci("uci\nisready\nposition fen 8/8/8/3K4/7k/8/6R1/5R2 w - - 0 1\ngo depth 2\n");
+        assert!(output[0].starts_with("id name"));
+        assert!(output.contains(&"option name Hash type spin default 16 min 1 max 65536".into()));
+        assert_eq!(
+            output[output.len() - 3..],
+            ["uciok", "readyok", "bestmove f1h1"]
+        );
+
+        let output = run_uci("isready\nquit\nisready\n");
+        assert_eq!(output, ["readyok"]);
+
+        // bad input is explained, and the engine keeps going
+        let output = run_uci(
+            "go depth x\nposition fen 8/8/8 w - - 0 1\nposition startpos moves e2e5\nisready\n",
+        );
+        assert_eq!(
+            output,
+            [
+                "info string go: invalid token 'x'",
+                "info string position: invalid FEN '8/8/8 w - - 0 1'",
+                "info string position: illegal move 'e2e5'",
+                "readyok"
+            ]
+        );
+    }
+
+    #[test]
+    fn debugging_commands() {
+        let output = run_uci("d\nposition startpos moves e2e4\nd\n");
+        assert!(
+            output
+                .contains(&"Fen: rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".into())
+        );
+        assert!(
+            output.contains(
+                &"Fen: rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".into()
+            )
+        );
+        assert_eq!(
+            output
+                .iter()
+                .filter(|line| line.starts_with("Key: "))
+                .count(),
+            2
+        );
+
+        let output = run_uci("go perft 3\n");
+        assert_eq!(output.len(), 22);
+        assert!(output.contains(&"e2e4: 600".into()));
+        assert_eq!(output.last().unwrap(), "Nodes searched: 8902");
+
+        let output = run_uci(
+            "position fen r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1\n\
+             go perft 2\n",
+        );
+        assert_eq!(output.last().unwrap(), "Nodes searched: 2039");
+
+        let output = run_uci("eval\nbench 1\n");
+        assert!(output.iter().any(|line| line.starts_with("Total")));
+        assert!(output.iter().any(|line| line.starts_with("Nodes searched")));
+        assert!(!output.iter().any(|line| line.starts_with("bestmove")));
+    }
+
+    // plays the knights out and back, which stays legal for the first few moves of any game
+    const MOCK_ENGINE: &str = r#"
+        plies=0
+        while read -r command rest; do
+            case "$command" in
+                uci)
+                    echo "id name Mock Engine"
+                    echo "id author Nobody"
+                    echo "option name Hash type spin default 1 min 1 max 8"
+                    echo "option name Play Style type combo default Solid var Solid var Risky"
+                    echo "uciok" ;;
+                isready) echo "readyok" ;;
+                position) set -- $rest; plies=$(($# > 2 ? $# - 2 : 0)) ;;
+                go)
+                    case $((plies % 4)) in
+                        0) mov=g1f3 ;; 1) mov=g8f6 ;; 2) mov=f3g1 ;; 3) mov=f6g8 ;;
+                    esac
+                    echo "info depth 1 score cp 13 nodes 20 pv $mov"
+                    echo "bestmove $mov" ;;
+                quit) exit 0 ;;
+            esac
+        done
+    "#;
+
+    #[test]
+    fn engine_replies_parse() {
+        assert_eq!(
+            EngineReply::parse("bestmove e7e8q ponder a2a1"),
+            Ok(EngineReply::BestMove {
+                mov: Some("e7e8q".to_string()),
+                ponder: Some("a2a1".to_string())
+            })
+        );
+        assert_eq!(
+            EngineReply::parse("bestmove (none)"),
+            Ok(EngineReply::BestMove {
+                mov: None,
+                ponder: None
+            })
+        );
+        assert_eq!(
+            EngineReply::parse("info depth 3 score mate -2 lowerbound pv e2e4 e7e5 string a b"),
+            Ok(EngineReply::Info(EngineInfo {
+                depth: Some(3),
+                score: Some(InfoScore::Mate(-2)),
+                pv: vec!["e2e4".to_string(), "e7e5".to_string()],
+                string: Some("a b".to_string()),
+                ..Default::default()
+            }))
+        );
+        assert!(EngineReply::parse("option name Hash type spin default 1").is_err());
+    }
+
+    #[test]
+    fn drive_engine_process() {
+        let mut engine = UciEngineProcess::spawn("sh", &["-c", MOCK_ENGINE]).unwrap();
+        assert_eq!((engine.name(), engine.author()), ("Mock Engine", "Nobody"));
+        assert_eq!(engine.options()[1].name, "Play Style");
+        assert_eq!(
+            engine.options()[1].kind,
+            UciOptionKind::Combo {
+                default: "Solid".to_string(),
+                vars: vec!["Solid".to_string(), "Risky".to_string()]
+            }
+        );
+
+        engine.set_option("Hash", Some("4")).unwrap();
+        engine.new_game().unwrap();
+        engine
+            .set_position(UciPosition::StartPos, &["e2e4".to_string()])
+            .unwrap();
+        let search = engine.go(&GoParams::default()).unwrap();
+        assert_eq!(search.best_move.as_deref(), Some("g8f6"));
+        assert_eq!(search.score(), Some(InfoScore::Centipawns(13)));
+        assert!(engine.quit().unwrap().success());
+    }
+
+    #[test]
+    fn match_against_mock_engine() {
+        let engine = UciEngineProcess::spawn("sh", &["-c", MOCK_ENGINE]).unwrap();
+        let config = MatchConfig {
+            games: 2,
+            bot_depth: 1,
+            max_plies: 4,
+            ..Default::default()
+        };
+        let mut pgn = Vec::new();
+        let score = MatchRunner::new(engine, config).run(&mut pgn).unwrap();
+        assert_eq!(
+            score,
+            MatchScore {
+                draws: 2,
+                ..Default::default()
+            }
+        );
+        let pgn = String::from_utf8(pgn).unwrap();
+        assert_eq!(pgn.matches("[Result \"1/2-1/2\"]").count(), 2);
+        assert!(pgn.contains("[Black \"Mock Engine\"]"));
+        assert!(pgn.contains("1. Nf3 "));
+
+        let board = SearchBoard::from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+        let knight_move = Bot::from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1")
+            .parse_move("b1d2")
+            .unwrap();
+        assert_eq!(knight_move.into_san(&board), "Nbd2");
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let start = |index| SearchBoard::chess960_start(index).unwrap().to_fen();
+        assert_eq!(start(518), SearchBoard::default().to_fen());
+        assert_eq!(
+            start(0),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert!(SearchBoard::chess960_start(960).is_none());
+
+        let game =
+            Bot::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+        let counts: Vec<u64> = (1..=3)
+            .map(|depth| {
+                game.perft_divide(depth)
+                    .iter()
+                    .map(|(_, nodes)| nodes)
+                    .sum()
+            })
+            .collect();
+        assert_eq!(counts, [21, 528, 12189]);
+
+        // the king lands on the rook's square when castling short
+        let fen = "1r2k1r1/1p4p1/8/8/8/8/1P4P1/1R2K1R1 w GBgb - 0 1";
+        let mut game = Bot::default();
+        game.set_config(SearchConfig {
+            chess960: true,
+            ..Default::default()
+        });
+        game.load_fen(fen).unwrap();
+        assert!(game.parse_move("e1c1").is_none());
+        let castle = game.parse_move("e1g1").unwrap();
+        assert_eq!(castle.move_type, MoveType::ShortCastle);
+        game.make_move(&castle);
+        let board = game.get_board();
+        assert_eq!(
+            board.to_fen(),
+            "1r2k1r1/1p4p1/8/8/8/8/1P4P1/1R3RK1 b kq - 1 1"
+        );
+        assert_eq!(
+            board.to_shredder_fen(),
+            "1r2k1r1/1p4p1/8/8/8/8/1P4P1/1R3RK1 b gb - 1 1"
+        );
+        assert_eq!(
+            board.zobrist,
+            SearchBoard::from_fen(&board.to_fen()).zobrist
+        );
+        let castle = game.parse_move("e8b8").unwrap();
+        assert_eq!(castle.move_type, MoveType::LongCastle);
+
+        // without UCI_Chess960 castling is written with the king's destination
+        let game = Bot::from_fen(fen);
+        assert_eq!(
+            game.parse_move("e1c1").unwrap().move_type,
+            MoveType::LongCastle
+        );
+
+        // X-FEN names the file when the castling rook isn't the outermost one
+        let board = SearchBoard::from_fen("4k3/8/8/8/8/8/8/R2RK3 w D - 0 1");
+        assert_eq!(
+            board.home_rooks(Side::White)[0],
+            Position::from_str("d1").unwrap()
+        );
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/R2RK3 w D - 0 1");
+
+        let output = run_uci(
+            "setoption name UCI_Chess960 value true\n\
+             position fen 1r2k1r1/1p4p1/8/8/8/8/1P4P1/1R2K1R1 w GBgb - 0 1 moves e1b1\nd\n",
+        );
+        assert!(output.contains(&"Fen: 1r2k1r1/1p4p1/8/8/8/8/1P4P1/2KR2R1 b kq - 1 1".into()));
+    }
+
+    #[test]
+    fn castling_rights_hash_apart() {
+        let hash = |rights| {
+            SearchBoard::from_fen(&format!("r3k2r/8/8/8/8/8/8/R3K2R w {rights} - 0 1")).zobrist
+        };
+        assert_ne!(hash("KQk"), hash("KQq"));
+        assert_ne!(hash("KQkq"), hash("KQk"));
+        assert_ne!(hash("KQkq"), hash("KQ"));
+
+        // losing a right updates the hash the same way hashing from scratch does
+        let mut game = Bot::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        game.make_move(&game.parse_move("a8b8").unwrap());
+        let board = game.get_board();
+        assert_eq!(board.to_fen(), "1r2k2r/8/8/8/8/8/8/R3K2R w KQk - 1 2");
+        assert_eq!(
+            board.zobrist,
+            SearchBoard::from_fen(&board.to_fen()).zobrist
+        );
+    }
 }
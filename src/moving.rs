@@ -26,6 +26,12 @@ pub struct Move {
     pub take: Option<Piece>,
 }
 
+/// What `SearchBoard::unmake_null` needs to take a null move back
+pub struct NullUnmove {
+    pub en_passant_square: Option<Position>,
+    pub zobrist: u64,
+}
+
 pub struct Unmove {
     pub mov: Move,
     pub en_passant_square: Option<Position>,
@@ -188,6 +194,64 @@ impl Move {
         buf
     }
 
+    /// Standard algebraic notation as used in PGN (e.g. `Nbd7`, `exd5`, `e8=Q+`), `board` is
+    /// the position the move is played in
+    pub fn into_san(self, board: &SearchBoard) -> String {
+        let mut san = String::new();
+        let is_capture = self.take.is_some() || self.move_type == MoveType::EnPassant;
+        match self.move_type {
+            MoveType::ShortCastle => san.push_str("O-O"),
+            MoveType::LongCastle => san.push_str("O-O-O"),
+            MoveType::Normal(PieceType::Pawn) | MoveType::Promotion(_) | MoveType::EnPassant => {
+                if is_capture {
+                    write!(san, "{}x", (self.from.x() + b'a') as char).unwrap();
+                }
+                write!(san, "{}", self.to).unwrap();
+                if let MoveType::Promotion(promoted_to) = self.move_type {
+                    write!(san, "={}", promoted_to.as_char()).unwrap();
+                }
+            }
+            MoveType::Normal(piece_type) => {
+                san.push(piece_type.as_char());
+                // only the pieces of the same kind that could go to the same square matter
+                let (pin_state, check_paths) = board.legal_data();
+                let rivals: Vec<Position> = board
+                    .find_all_moves(pin_state, check_paths, false)
+                    .into_iter()
+                    .filter(|mov| {
+                        mov.move_type == self.move_type
+                            && mov.to == self.to
+                            && mov.from != self.from
+                    })
+                    .map(|mov| mov.from)
+                    .collect();
+                let same_file = rivals.iter().any(|from| from.x() == self.from.x());
+                let same_rank = rivals.iter().any(|from| from.y() == self.from.y());
+                if !rivals.is_empty() && (!same_file || same_rank) {
+                    san.push((self.from.x() + b'a') as char);
+                }
+                if same_file {
+                    san.push((self.from.y() + b'1') as char);
+                }
+                if is_capture {
+                    san.push('x');
+                }
+                write!(san, "{}", self.to).unwrap();
+            }
+        }
+
+        let mut after = board.clone();
+        after.make(&self);
+        let (pin_state, check_paths) = after.legal_data();
+        if check_paths.is_check() {
+            let is_mate = after
+                .find_all_moves(pin_state, check_paths, false)
+                .is_empty();
+            san.push(if is_mate { '#' } else { '+' });
+        }
+        san
+    }
+
     pub fn into_algebraic(self) -> String {
         match self.move_type {
             MoveType::Normal(piece_type) => {
@@ -1,4 +1,4 @@
-use crate::board_repr::BoardRepr;
+use crate::board_repr::{BoardRepr, QUEEN, ROOK};
 use crate::piece::Piece;
 use crate::search_data::{CheckPath, PinState};
 use crate::search_masks::{KING_MASKS, KNIGHT_MASKS, choose_pawn_take_mask};
@@ -348,33 +348,43 @@ pub fn find_king<const CAP: usize>(
         && !gen_only_takes
     {
         let castle_rights = state.side_castle_rights(side);
-        let short = 0x60 << (side.home_y() * 8);
-        let long_occupy = 0xe << (side.home_y() * 8);
-        let long_attack = 0xc << (side.home_y() * 8);
-
-        if castle_rights.0
-            && long_occupy & (allies | enemies) == 0
-            && long_attack & attacked_squares == 0
-        {
-            moves.push(Move::new(
-                pos,
-                pos.with_x(2).unwrap(),
-                MoveType::LongCastle,
-                None,
-            ));
-        }
-
-        if castle_rights.1 && short & (allies | enemies | attacked_squares) == 0 {
-            moves.push(Move::new(
-                pos,
-                pos.with_x(6).unwrap(),
-                MoveType::ShortCastle,
-                None,
-            ));
+        let occupied = allies | enemies;
+        let enemy_bits = state.side_bitboards(side.opposite());
+        let castles = [
+            (castle_rights.0, true, MoveType::LongCastle),
+            (castle_rights.1, false, MoveType::ShortCastle),
+        ];
+        for (allowed, long, move_type) in castles {
+            if !allowed {
+                continue;
+            }
+            let (rook_from, king_to, rook_to) = state.castling_squares(side, long);
+            let king_path = rank_span(pos, king_to);
+            // everything the king and the rook pass has to be empty, apart from the two of them
+            let crossed = (king_path | rank_span(rook_from, rook_to))
+                & !(pos.as_mask() | rook_from.as_mask());
+            if crossed & occupied != 0 || king_path & attacked_squares != 0 {
+                continue;
+            }
+            // in Chess960 the castling rook itself can be what shields the king's new square
+            let unshielded = MAGIC_MOVER
+                .get_rook(king_to, occupied & !(pos.as_mask() | rook_from.as_mask()))
+                .bitboard;
+            if unshielded & (enemy_bits[ROOK] | enemy_bits[QUEEN]) != 0 {
+                continue;
+            }
+            let to = if state.chess960 { rook_from } else { king_to };
+            moves.push(Move::new(pos, to, move_type, None));
         }
     }
 }
 
+/// Every square from `a` to `b` on the same rank, both included
+fn rank_span(a: Position, b: Position) -> u64 {
+    let (low, high) = (a.index().min(b.index()), a.index().max(b.index()));
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
+
 pub fn find_rook<const CAP: usize>(
     moves: &mut ArrayVec<Move, CAP>,
     pos: Position,
@@ -0,0 +1,118 @@
+use crate::board::BoardState;
+use crate::board_repr::{BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK};
+use crate::engine::constants::MATERIAL_WEIGHT;
+use crate::engine::evaluate::get_raw_material;
+use crate::magic_bitboards::MAGIC_MOVER;
+use crate::moving::{Move, MoveType};
+use crate::piece::{PieceType, Side};
+use crate::position::Position;
+use crate::search_masks::{KING_MASKS, KNIGHT_MASKS, choose_pawn_take_mask};
+
+/// The pieces in the order they join an exchange, least valuable first
+const EXCHANGE_ORDER: [(usize, PieceType); 6] = [
+    (PAWN, PieceType::Pawn),
+    (KNIGHT, PieceType::Knight),
+    (BISHOP, PieceType::Bishop),
+    (ROOK, PieceType::Rook),
+    (QUEEN, PieceType::Queen),
+    (KING, PieceType::King),
+];
+
+impl BoardState {
+    /// Static exchange evaluation: the material the side to move wins with `mov` if both sides
+    /// keep recapturing on its target square with their least valuable piece, and may stop
+    /// whenever that is better for them. Pieces behind a slider join once it has left. Pins
+    /// are ignored. In the units of the evaluation.
+    pub fn see(&self, mov: &Move) -> i64 {
+        if let MoveType::LongCastle | MoveType::ShortCastle = mov.move_type {
+            return 0;
+        }
+        let to = mov.to();
+        let mut occupied = self.side_bitboards(Side::White).combined()
+            | self.side_bitboards(Side::Black).combined();
+        occupied ^= mov.from().as_mask();
+
+        if mov.move_type == MoveType::EnPassant {
+            occupied ^= to.with_y(self.side.pers_y(4)).unwrap().as_mask();
+        }
+        // gains[i] is what the side making the i-th capture has won once it's done
+        let mut gains = [0; 32];
+        gains[0] = gain(mov);
+        // the piece standing on the square, the next capture takes it
+        let mut on_square = get_raw_material(mov.promote_to().unwrap_or(mov.piece_type()));
+        let mut side = self.side.opposite();
+        let mut captures = 0;
+
+        loop {
+            let attackers = self.attackers(to, occupied) & occupied;
+            let bitboards = self.side_bitboards(side);
+            let Some((square, piece)) = EXCHANGE_ORDER
+                .iter()
+                .find(|(index, _)| attackers & bitboards[*index] != 0)
+                .map(|(index, piece)| ((attackers & bitboards[*index]).trailing_zeros(), *piece))
+            else {
+                break;
+            };
+            // the king can only take if nothing takes it back
+            let defenders = attackers & self.side_bitboards(side.opposite()).combined();
+            if piece == PieceType::King && defenders != 0 {
+                break;
+            }
+            captures += 1;
+            gains[captures] = on_square - gains[captures - 1];
+            on_square = get_raw_material(piece);
+            occupied ^= 1 << square;
+            side = side.opposite();
+            if captures == gains.len() - 1 {
+                break;
+            }
+        }
+        // either side stops taking as soon as going on would lose more
+        while captures > 0 {
+            gains[captures - 1] = -(-gains[captures - 1]).max(gains[captures]);
+            captures -= 1;
+        }
+        gains[0] * MATERIAL_WEIGHT
+    }
+
+    /// Whether `mov` wins at least `threshold` in the exchange on its target square
+    pub fn see_ge(&self, mov: &Move, threshold: i64) -> bool {
+        self.see(mov) >= threshold
+    }
+
+    /// Every piece of either side attacking `square` when only `occupied` blocks the sliders
+    fn attackers(&self, square: Position, occupied: u64) -> u64 {
+        let white = self.side_bitboards(Side::White);
+        let black = self.side_bitboards(Side::Black);
+        let index = *square as usize;
+        // a white pawn attacks the square a black pawn on it would attack in reverse
+        let pawns = choose_pawn_take_mask(Side::Black)[index].sum & white[PAWN]
+            | choose_pawn_take_mask(Side::White)[index].sum & black[PAWN];
+        let knights = KNIGHT_MASKS[index].sum & (white[KNIGHT] | black[KNIGHT]);
+        let kings = KING_MASKS[index].sum & (white[KING] | black[KING]);
+        let queens = white[QUEEN] | black[QUEEN];
+        let rooks =
+            MAGIC_MOVER.get_rook(square, occupied).bitboard & (white[ROOK] | black[ROOK] | queens);
+        let bishops = MAGIC_MOVER.get_bishop(square, occupied).bitboard
+            & (white[BISHOP] | black[BISHOP] | queens);
+        pawns | knights | kings | rooks | bishops
+    }
+}
+
+/// Material `mov` wins before anything is taken back, in the units of the evaluation
+pub fn material_gain(mov: &Move) -> i64 {
+    gain(mov) * MATERIAL_WEIGHT
+}
+
+/// The piece `mov` takes and what its promotion adds, in pawns
+fn gain(mov: &Move) -> i64 {
+    let taken = match (mov.take, mov.move_type) {
+        (Some(taken), _) => get_raw_material(taken.role()),
+        (None, MoveType::EnPassant) => get_raw_material(PieceType::Pawn),
+        _ => 0,
+    };
+    let promotion = mov.promote_to().map_or(0, |promoted| {
+        get_raw_material(promoted) - get_raw_material(PieceType::Pawn)
+    });
+    taken + promotion
+}
@@ -0,0 +1,398 @@
+use std::{
+    ffi::OsStr,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    sync::mpsc::{Receiver, RecvTimeoutError, channel},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    engine::search_info::InfoScore,
+    uci::{
+        command::{GoParams, Tokens, UciCommand, UciParseError, UciPosition},
+        options::UciOptionKind,
+    },
+};
+
+/// How long an engine may take to answer `uci` and `isready`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long an engine may take to exit after `quit` before it gets killed
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Lines an engine may send, in front of which unknown tokens are skipped
+const REPLIES: [&str; 8] = [
+    "id",
+    "uciok",
+    "readyok",
+    "bestmove",
+    "copyprotection",
+    "registration",
+    "info",
+    "option",
+];
+
+const INFO_KEYWORDS: [&str; 17] = [
+    "depth",
+    "seldepth",
+    "time",
+    "nodes",
+    "pv",
+    "multipv",
+    "score",
+    "currmove",
+    "currmovenumber",
+    "hashfull",
+    "nps",
+    "tbhits",
+    "sbhits",
+    "cpuload",
+    "string",
+    "refutation",
+    "currline",
+];
+
+/// What an engine sends to the GUI. Moves stay in long algebraic notation, only the position
+/// they are played in can make sense of them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineReply {
+    IdName(String),
+    IdAuthor(String),
+    UciOk,
+    ReadyOk,
+    BestMove {
+        mov: Option<String>,
+        ponder: Option<String>,
+    },
+    CopyProtection(String),
+    Registration(String),
+    Info(EngineInfo),
+    Option(EngineOption),
+}
+
+/// An `info` line of an external engine
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EngineInfo {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<InfoScore>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub time: Option<Duration>,
+    pub hashfull: Option<u32>,
+    pub currmove: Option<String>,
+    pub currmovenumber: Option<u32>,
+    pub pv: Vec<String>,
+    pub string: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineOption {
+    pub name: String,
+    pub kind: UciOptionKind,
+}
+
+/// The answer to a `go`: the move and everything the engine reported on the way
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EngineSearch {
+    pub best_move: Option<String>,
+    pub ponder: Option<String>,
+    pub infos: Vec<EngineInfo>,
+}
+
+impl EngineSearch {
+    /// The last score the engine reported
+    pub fn score(&self) -> Option<InfoScore> {
+        self.infos.iter().rev().find_map(|info| info.score)
+    }
+}
+
+impl EngineReply {
+    pub fn parse(line: &str) -> Result<Self, UciParseError> {
+        let mut parts = line.split_whitespace().peekable();
+        if parts.peek().is_none() {
+            return Err(UciParseError::Empty);
+        }
+        let Some(reply) = parts.find_map(|part| REPLIES.into_iter().find(|r| *r == part)) else {
+            return Err(UciParseError::UnknownCommand(line.trim().to_string()));
+        };
+        let mut tokens = Tokens::new(reply, parts);
+        let reply = match reply {
+            "uciok" => EngineReply::UciOk,
+            "readyok" => EngineReply::ReadyOk,
+            "id" => match tokens.expect("name or author")? {
+                "name" => EngineReply::IdName(tokens.rest()),
+                "author" => EngineReply::IdAuthor(tokens.rest()),
+                token => return Err(tokens.invalid(token)),
+            },
+            "bestmove" => {
+                // engines without a move send a null move or `(none)`
+                let mov = match tokens.expect("move")? {
+                    "0000" | "(none)" => None,
+                    mov => Some(tokens.long_algebraic(mov)?),
+                };
+                let ponder = if tokens.keyword("ponder") {
+                    let ponder = tokens.expect("ponder move")?;
+                    Some(tokens.long_algebraic(ponder)?)
+                } else {
+                    None
+                };
+                EngineReply::BestMove { mov, ponder }
+            }
+            "copyprotection" => EngineReply::CopyProtection(tokens.rest()),
+            "registration" => EngineReply::Registration(tokens.rest()),
+            "info" => EngineReply::Info(EngineInfo::parse(&mut tokens)?),
+            "option" => {
+                if !tokens.keyword("name") {
+                    return Err(tokens.missing("name"));
+                }
+                let name = tokens.words_until("type");
+                if !tokens.keyword("type") {
+                    return Err(tokens.missing("type"));
+                }
+                let kind = UciOptionKind::parse(&mut tokens)?;
+                EngineReply::Option(EngineOption { name, kind })
+            }
+            _ => unreachable!("every entry of REPLIES is handled"),
+        };
+        Ok(reply)
+    }
+}
+
+impl EngineInfo {
+    fn parse(tokens: &mut Tokens) -> Result<Self, UciParseError> {
+        let mut info = EngineInfo::default();
+        while let Some(keyword) = tokens.next() {
+            match keyword {
+                "depth" => info.depth = Some(tokens.value("depth")?),
+                "seldepth" => info.seldepth = Some(tokens.value("seldepth")?),
+                "multipv" => info.multipv = Some(tokens.value("multipv")?),
+                "nodes" => info.nodes = Some(tokens.value("nodes")?),
+                "nps" => info.nps = Some(tokens.value("nps")?),
+                "time" => info.time = Some(tokens.millis("time")?),
+                "hashfull" => info.hashfull = Some(tokens.value("hashfull")?),
+                "currmovenumber" => info.currmovenumber = Some(tokens.value("currmovenumber")?),
+                "currmove" => {
+                    let mov = tokens.expect("currmove")?;
+                    info.currmove = Some(tokens.long_algebraic(mov)?);
+                }
+                "score" => {
+                    info.score = Some(match tokens.expect("cp or mate")? {
+                        "cp" => InfoScore::Centipawns(tokens.value("centipawns")?),
+                        "mate" => InfoScore::Mate(tokens.value("moves to mate")?),
+                        token => return Err(tokens.invalid(token)),
+                    });
+                    // lowerbound and upperbound are skipped like any other unknown token
+                }
+                "pv" => {
+                    while let Some(mov) = tokens.next_if(|part| !INFO_KEYWORDS.contains(&part)) {
+                        info.pv.push(tokens.long_algebraic(mov)?);
+                    }
+                }
+                // the rest of the line is free form text
+                "string" => info.string = Some(tokens.rest()),
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// An external UCI engine running as a child process. Its output is parsed on a reader thread,
+/// lines that aren't valid UCI are dropped.
+pub struct UciEngineProcess {
+    child: Child,
+    stdin: ChildStdin,
+    replies: Receiver<EngineReply>,
+    reader: Option<JoinHandle<()>>,
+    name: String,
+    author: String,
+    options: Vec<EngineOption>,
+}
+
+impl UciEngineProcess {
+    /// Starts `program` and goes through the `uci` handshake
+    pub fn spawn(program: impl AsRef<OsStr>, args: &[&str]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, replies) = channel();
+        let reader = thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let Ok(reply) = EngineReply::parse(&line) else {
+                    continue;
+                };
+                if sender.send(reply).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Self {
+            child,
+            stdin,
+            replies,
+            reader: Some(reader),
+            name: String::new(),
+            author: String::new(),
+            options: Vec::new(),
+        };
+        engine.handshake()?;
+        Ok(engine)
+    }
+
+    fn handshake(&mut self) -> io::Result<()> {
+        self.send(UciCommand::Uci)?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            match self.next_reply(Some(deadline))? {
+                EngineReply::IdName(name) => self.name = name,
+                EngineReply::IdAuthor(author) => self.author = author,
+                EngineReply::Option(option) => self.options.push(option),
+                EngineReply::UciOk => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    /// The options the engine declared during the handshake
+    pub fn options(&self) -> &[EngineOption] {
+        &self.options
+    }
+
+    pub fn send(&mut self, command: UciCommand) -> io::Result<()> {
+        self.stdin.write_all(command.to_string().as_bytes())?;
+        self.stdin.flush()
+    }
+
+    /// Waits for the next reply, until `deadline` if there is one
+    fn next_reply(&self, deadline: Option<Instant>) -> io::Result<EngineReply> {
+        let reply = match deadline {
+            Some(deadline) => self
+                .replies
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self
+                .replies
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        reply.map_err(|err| match err {
+            RecvTimeoutError::Timeout => {
+                io::Error::new(ErrorKind::TimedOut, "the engine didn't answer in time")
+            }
+            RecvTimeoutError::Disconnected => {
+                io::Error::new(ErrorKind::UnexpectedEof, "the engine exited")
+            }
+        })
+    }
+
+    /// Sends `isready` and waits for `readyok`
+    pub fn is_ready(&mut self) -> io::Result<()> {
+        self.send(UciCommand::IsReady)?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.next_reply(Some(deadline))? != EngineReply::ReadyOk {}
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> io::Result<()> {
+        self.send(UciCommand::SetOption {
+            name: name.to_string(),
+            value: value.map(|value| value.to_string()),
+        })
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send(UciCommand::UciNewGame)?;
+        self.is_ready()
+    }
+
+    pub fn set_position(&mut self, position: UciPosition, moves: &[String]) -> io::Result<()> {
+        self.send(UciCommand::Position {
+            position,
+            moves: moves.to_vec(),
+        })
+    }
+
+    /// Starts searching the current position and waits for `bestmove`. For infinite searches,
+    /// use `start_search`, `stop` and `wait_for_best_move` instead.
+    pub fn go(&mut self, params: &GoParams) -> io::Result<EngineSearch> {
+        self.start_search(params)?;
+        self.wait_for_best_move(None)
+    }
+
+    pub fn start_search(&mut self, params: &GoParams) -> io::Result<()> {
+        self.send(UciCommand::Go(params.clone()))
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.send(UciCommand::Stop)
+    }
+
+    /// Collects `info` lines until `bestmove` arrives, or `timeout` passes
+    pub fn wait_for_best_move(&mut self, timeout: Option<Duration>) -> io::Result<EngineSearch> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut search = EngineSearch::default();
+        loop {
+            match self.next_reply(deadline)? {
+                EngineReply::Info(info) => search.infos.push(info),
+                EngineReply::BestMove { mov, ponder } => {
+                    search.best_move = mov;
+                    search.ponder = ponder;
+                    return Ok(search);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends `quit` and waits for the engine to exit, killing it if it doesn't
+    pub fn quit(mut self) -> io::Result<ExitStatus> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> io::Result<ExitStatus> {
+        // the engine may already be gone, in which case there is nobody to tell
+        self.send(UciCommand::Quit).ok();
+        let deadline = Instant::now() + QUIT_TIMEOUT;
+        let status = loop {
+            if let Some(status) = self.child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                self.child.kill()?;
+                break self.child.wait()?;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        if let Some(reader) = self.reader.take() {
+            reader.join().ok();
+        }
+        Ok(status)
+    }
+}
+
+impl Drop for UciEngineProcess {
+    fn drop(&mut self) {
+        if self.reader.is_some() {
+            self.shutdown().ok();
+        }
+    }
+}
@@ -1,5 +1,19 @@
-use crate::moving::Move;
-use std::fmt::{Display, Write};
+use crate::{
+    engine::{
+        search_info::{InfoScore, SearchInfo},
+        time_manager::TimeManager,
+    },
+    moving::Move,
+    piece::Side,
+    position::Position,
+    uci::options::UciOptionKind,
+};
+use std::{
+    fmt::{Display, Write},
+    iter::Peekable,
+    str::{FromStr, SplitWhitespace},
+    time::Duration,
+};
 
 pub enum UciCommand {
     Uci,
@@ -9,21 +23,48 @@ pub enum UciCommand {
         name: String,
         value: Option<String>,
     },
+    Register(UciRegistration),
     UciNewGame,
     Position {
         position: UciPosition,
         moves: Vec<String>,
     },
-    GoDepth(u32),
+    Go(GoParams),
     Id {
         name: String,
         author: String,
     },
-    BestMove(Move),
+    BestMove {
+        mov: Option<Move>,
+        ponder: Option<Move>,
+    },
+    Option {
+        name: String,
+        kind: UciOptionKind,
+    },
+    Info(SearchInfo),
+    /// Free form text for the GUI, sent as `info string ...`
+    InfoString(String),
     UciOk,
     ReadyOk,
     Stop,
+    PonderHit,
     Quit,
+    /// `d`, not part of UCI: shows the board, its FEN and Zobrist key
+    ShowBoard,
+    /// `eval`, not part of UCI: shows the terms of the static evaluation
+    Eval,
+    /// `bench`, not part of UCI: searches a fixed set of positions and reports the speed
+    Bench {
+        depth: Option<u32>,
+    },
+    /// Output of the debugging commands, sent as it is
+    Raw(String),
+}
+
+pub enum UciRegistration {
+    Later,
+    Name { name: String, code: String },
 }
 
 pub enum UciPosition {
@@ -31,42 +72,339 @@ pub enum UciPosition {
     Fen(String),
 }
 
+/// Every parameter that can follow `go`, all of them optional
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GoParams {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub mate: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub infinite: bool,
+    pub ponder: bool,
+    pub searchmoves: Vec<String>,
+    /// Not part of UCI: count the leaf nodes below every move instead of searching
+    pub perft: Option<u32>,
+}
+
+const GO_KEYWORDS: [&str; 13] = [
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+    "ponder",
+    "searchmoves",
+    "perft",
+];
+
+impl GoParams {
+    fn parse(tokens: &mut Tokens) -> Result<Self, UciParseError> {
+        let mut params = GoParams::default();
+        while let Some(keyword) = tokens.next() {
+            match keyword {
+                "wtime" => params.wtime = Some(tokens.millis("wtime")?),
+                "btime" => params.btime = Some(tokens.millis("btime")?),
+                "winc" => params.winc = Some(tokens.millis("winc")?),
+                "binc" => params.binc = Some(tokens.millis("binc")?),
+                "movetime" => params.movetime = Some(tokens.millis("movetime")?),
+                "movestogo" => params.movestogo = Some(tokens.value("movestogo")?),
+                "depth" => params.depth = Some(tokens.value("depth")?),
+                "nodes" => params.nodes = Some(tokens.value("nodes")?),
+                "mate" => params.mate = Some(tokens.value("mate")?),
+                "perft" => params.perft = Some(tokens.value("perft")?),
+                "infinite" => params.infinite = true,
+                "ponder" => params.ponder = true,
+                // the moves run up to the next keyword. They are checked against the position
+                // when searching, which leaves out anything that isn't a legal move
+                "searchmoves" => {
+                    while let Some(mov) = tokens.next_if(|part| !GO_KEYWORDS.contains(&part)) {
+                        params.searchmoves.push(mov.to_string());
+                    }
+                }
+                // unknown tokens are ignored, as the UCI spec asks
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+
+    pub fn time_left(&self, side: Side) -> Option<Duration> {
+        match side {
+            Side::White => self.wtime,
+            Side::Black => self.btime,
+        }
+    }
+
+    pub fn increment(&self, side: Side) -> Option<Duration> {
+        match side {
+            Side::White => self.winc,
+            Side::Black => self.binc,
+        }
+    }
+
+    /// How long `side` may think about this move by the clock, `None` if the GUI didn't send
+    /// one. A fixed `movetime` isn't planned here. When pondering, the clock starts with
+    /// `ponderhit`.
+    pub fn time_manager(&self, side: Side, move_overhead: Duration) -> Option<TimeManager> {
+        if self.movetime.is_some() {
+            return None;
+        }
+        Some(TimeManager::new(
+            self.time_left(side)?,
+            self.increment(side).unwrap_or_default(),
+            self.movestogo,
+            move_overhead,
+        ))
+    }
+}
+
+/// Commands an engine may receive, in front of which unknown tokens are skipped
+const COMMANDS: [&str; 16] = [
+    "uci",
+    "debug",
+    "isready",
+    "setoption",
+    "register",
+    "ucinewgame",
+    "position",
+    "go",
+    "stop",
+    "ponderhit",
+    "quit",
+    "uciok",
+    "readyok",
+    "d",
+    "eval",
+    "bench",
+];
+
+/// Why a line couldn't be parsed into a [`UciCommand`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciParseError {
+    /// Nothing but whitespace, which GUIs are allowed to send
+    Empty,
+    /// None of the tokens is a command
+    UnknownCommand(String),
+    /// The line ended before `expected` came
+    Missing {
+        command: &'static str,
+        expected: &'static str,
+    },
+    /// `token` doesn't fit where it is
+    Invalid {
+        command: &'static str,
+        token: String,
+    },
+}
+
+impl Display for UciParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UciParseError::Empty => write!(f, "empty command"),
+            UciParseError::UnknownCommand(line) => write!(f, "unknown command: {}", line),
+            UciParseError::Missing { command, expected } => {
+                write!(f, "{}: missing {}", command, expected)
+            }
+            UciParseError::Invalid { command, token } => {
+                write!(f, "{}: invalid token '{}'", command, token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UciParseError {}
+
+/// The tokens following a command, errors are reported against that command
+pub(super) struct Tokens<'a> {
+    command: &'static str,
+    parts: Peekable<SplitWhitespace<'a>>,
+}
+
+impl<'a> Tokens<'a> {
+    pub(super) fn new(command: &'static str, parts: Peekable<SplitWhitespace<'a>>) -> Self {
+        Self { command, parts }
+    }
+
+    pub(super) fn next(&mut self) -> Option<&'a str> {
+        self.parts.next()
+    }
+
+    pub(super) fn next_if(&mut self, condition: impl FnOnce(&'a str) -> bool) -> Option<&'a str> {
+        self.parts.next_if(|part| condition(part))
+    }
+
+    /// Consumes the next token if it is `keyword`
+    pub(super) fn keyword(&mut self, keyword: &str) -> bool {
+        self.parts.next_if_eq(&keyword).is_some()
+    }
+
+    pub(super) fn expect(&mut self, expected: &'static str) -> Result<&'a str, UciParseError> {
+        self.next().ok_or_else(|| self.missing(expected))
+    }
+
+    pub(super) fn missing(&self, expected: &'static str) -> UciParseError {
+        UciParseError::Missing {
+            command: self.command,
+            expected,
+        }
+    }
+
+    pub(super) fn invalid(&self, token: &str) -> UciParseError {
+        UciParseError::Invalid {
+            command: self.command,
+            token: token.to_string(),
+        }
+    }
+
+    pub(super) fn value<T: FromStr>(&mut self, expected: &'static str) -> Result<T, UciParseError> {
+        let token = self.expect(expected)?;
+        token.parse().map_err(|_| self.invalid(token))
+    }
+
+    // some GUIs send negative times when the clock has run out
+    pub(super) fn millis(&mut self, expected: &'static str) -> Result<Duration, UciParseError> {
+        let millis: i64 = self.value(expected)?;
+        Ok(Duration::from_millis(millis.max(0) as u64))
+    }
+
+    /// Checks that `token` looks like a move, whether it is legal depends on the position
+    pub(super) fn long_algebraic(&self, token: &str) -> Result<String, UciParseError> {
+        let squares = token.get(..2).zip(token.get(2..4));
+        let promotion = token.get(4..).unwrap_or_default();
+        match squares {
+            Some((from, to))
+                if Position::from_str(from).is_some()
+                    && Position::from_str(to).is_some()
+                    && matches!(promotion, "" | "n" | "b" | "r" | "q") =>
+            {
+                Ok(token.to_string())
+            }
+            _ => Err(self.invalid(token)),
+        }
+    }
+
+    /// Joins the tokens up to `keyword` (or the end of the line), e.g. names with spaces
+    pub(super) fn words_until(&mut self, keyword: &str) -> String {
+        self.words_until_any(&[keyword])
+    }
+
+    pub(super) fn words_until_any(&mut self, keywords: &[&str]) -> String {
+        let mut words = Vec::new();
+        while let Some(word) = self.parts.next_if(|part| !keywords.contains(part)) {
+            words.push(word);
+        }
+        words.join(" ")
+    }
+
+    pub(super) fn rest(&mut self) -> String {
+        self.parts.by_ref().collect::<Vec<_>>().join(" ")
+    }
+}
+
 impl UciCommand {
-    pub fn parse(command: &str) -> Option<Self> {
-        let parts: Vec<&str> = command.split_whitespace().collect();
-        match parts[0] {
-            "uci" => Some(UciCommand::Uci),
-            "uciok" => Some(UciCommand::UciOk),
-            "readyok" => Some(UciCommand::ReadyOk),
-            "stop" => Some(UciCommand::Stop),
-            "quit" => Some(UciCommand::Quit),
-            "isready" => Some(UciCommand::IsReady),
-            "ucinewgame" => Some(UciCommand::UciNewGame),
-            "debug" => Some(UciCommand::Debug(parts[1] == "on")),
+    pub fn parse(line: &str) -> Result<Self, UciParseError> {
+        let mut parts = line.split_whitespace().peekable();
+        if parts.peek().is_none() {
+            return Err(UciParseError::Empty);
+        }
+        // unknown tokens in front of a command are skipped, as the UCI spec asks
+        let Some(command) = parts.find_map(|part| COMMANDS.into_iter().find(|c| *c == part)) else {
+            return Err(UciParseError::UnknownCommand(line.trim().to_string()));
+        };
+        let mut tokens = Tokens::new(command, parts);
+        let command = match command {
+            "uci" => UciCommand::Uci,
+            "uciok" => UciCommand::UciOk,
+            "readyok" => UciCommand::ReadyOk,
+            "stop" => UciCommand::Stop,
+            "ponderhit" => UciCommand::PonderHit,
+            "quit" => UciCommand::Quit,
+            "d" => UciCommand::ShowBoard,
+            "eval" => UciCommand::Eval,
+            "bench" => UciCommand::Bench {
+                depth: match tokens.next() {
+                    Some(depth) => Some(depth.parse().map_err(|_| tokens.invalid(depth))?),
+                    None => None,
+                },
+            },
+            "isready" => UciCommand::IsReady,
+            "ucinewgame" => UciCommand::UciNewGame,
+            "debug" => match tokens.expect("on or off")? {
+                "on" => UciCommand::Debug(true),
+                "off" => UciCommand::Debug(false),
+                token => return Err(tokens.invalid(token)),
+            },
             "setoption" => {
-                if parts[1] != "name" {
-                    return None;
+                if !tokens.keyword("name") {
+                    return Err(UciParseError::Missing {
+                        command: "setoption",
+                        expected: "name",
+                    });
+                }
+                // both the name and the value may contain spaces
+                let name = tokens.words_until("value");
+                if name.is_empty() {
+                    return Err(UciParseError::Missing {
+                        command: "setoption",
+                        expected: "option name",
+                    });
                 }
-                let name = parts[2];
-                if parts[3] != "value" {
-                    return None;
+                let value = tokens.keyword("value").then(|| tokens.rest());
+                UciCommand::SetOption { name, value }
+            }
+            "register" => match tokens.expect("later or name")? {
+                "later" => UciCommand::Register(UciRegistration::Later),
+                "name" => {
+                    let name = tokens.words_until("code");
+                    if !tokens.keyword("code") {
+                        return Err(UciParseError::Missing {
+                            command: "register",
+                            expected: "code",
+                        });
+                    }
+                    let code = tokens.rest();
+                    UciCommand::Register(UciRegistration::Name { name, code })
                 }
-                let value = parts.get(4).map(|v| v.to_string());
-                Some(UciCommand::SetOption {
-                    name: name.to_string(),
-                    value,
-                })
-            }
-            "go" => {
-                if parts[1] != "depth" {
-                    return None;
+                token => return Err(tokens.invalid(token)),
+            },
+            "position" => {
+                let position = match tokens.expect("startpos or fen")? {
+                    "startpos" => UciPosition::StartPos,
+                    "fen" => {
+                        let fen = tokens.words_until("moves");
+                        if fen.is_empty() {
+                            return Err(UciParseError::Missing {
+                                command: "position",
+                                expected: "FEN",
+                            });
+                        }
+                        UciPosition::Fen(fen)
+                    }
+                    token => return Err(tokens.invalid(token)),
+                };
+                tokens.words_until("moves");
+                let mut moves = Vec::new();
+                if tokens.keyword("moves") {
+                    while let Some(mov) = tokens.next() {
+                        moves.push(tokens.long_algebraic(mov)?);
+                    }
                 }
-                let depth = parts[2].parse().ok()?;
-                Some(UciCommand::GoDepth(depth))
+                UciCommand::Position { position, moves }
             }
-            "bestmove" => None,
-            _ => None,
-        }
+            "go" => UciCommand::Go(GoParams::parse(&mut tokens)?),
+            _ => unreachable!("every entry of COMMANDS is handled"),
+        };
+        Ok(command)
     }
 
     pub fn to_string(&self) -> String {
@@ -76,6 +414,7 @@ impl UciCommand {
             UciCommand::UciOk => writeln!(buf, "uciok").unwrap(),
             UciCommand::ReadyOk => writeln!(buf, "readyok").unwrap(),
             UciCommand::Stop => writeln!(buf, "stop").unwrap(),
+            UciCommand::PonderHit => writeln!(buf, "ponderhit").unwrap(),
             UciCommand::Quit => writeln!(buf, "quit").unwrap(),
             UciCommand::IsReady => writeln!(buf, "isready").unwrap(),
             UciCommand::UciNewGame => writeln!(buf, "ucinewgame").unwrap(),
@@ -85,23 +424,46 @@ impl UciCommand {
             UciCommand::SetOption { name, value } => {
                 write!(buf, "setoption name {}", name,).unwrap();
                 if let Some(value) = value {
-                    write!(buf, "value {}", value).unwrap();
+                    write!(buf, " value {}", value).unwrap();
                 }
                 writeln!(buf).unwrap();
             }
-            UciCommand::GoDepth(depth) => writeln!(buf, "go depth {}", depth).unwrap(),
+            UciCommand::Register(UciRegistration::Later) => {
+                writeln!(buf, "register later").unwrap()
+            }
+            UciCommand::Register(UciRegistration::Name { name, code }) => {
+                writeln!(buf, "register name {} code {}", name, code).unwrap()
+            }
+            UciCommand::Go(params) => writeln!(buf, "go{}", params).unwrap(),
             UciCommand::Id { name, author } => {
                 writeln!(buf, "id name {}", name).unwrap();
                 writeln!(buf, "id author {}", author).unwrap();
             }
-            UciCommand::BestMove(mov) => {
-                writeln!(buf, "bestmove {}", mov.into_long_algebraic()).unwrap()
+            UciCommand::BestMove { mov, ponder } => {
+                // the UCI spec uses a null move when there is nothing to play
+                let mov = mov.map_or("0000".to_string(), |mov| mov.into_long_algebraic());
+                write!(buf, "bestmove {}", mov).unwrap();
+                if let Some(ponder) = ponder {
+                    write!(buf, " ponder {}", ponder.into_long_algebraic()).unwrap();
+                }
+                writeln!(buf).unwrap();
+            }
+            UciCommand::Option { name, kind } => {
+                writeln!(buf, "option name {} type {}", name, kind).unwrap()
             }
+            UciCommand::Info(info) => writeln!(buf, "info{}", DisplayInfo(info)).unwrap(),
+            UciCommand::InfoString(text) => writeln!(buf, "info string {}", text).unwrap(),
+            UciCommand::ShowBoard => writeln!(buf, "d").unwrap(),
+            UciCommand::Eval => writeln!(buf, "eval").unwrap(),
+            UciCommand::Bench { depth: None } => writeln!(buf, "bench").unwrap(),
+            UciCommand::Bench { depth: Some(depth) } => writeln!(buf, "bench {}", depth).unwrap(),
+            UciCommand::Raw(text) => writeln!(buf, "{}", text.trim_end()).unwrap(),
             UciCommand::Position { position, moves } => {
-                writeln!(buf, "position {}", position).unwrap();
+                write!(buf, "position {}", position).unwrap();
                 if !moves.is_empty() {
-                    writeln!(buf, "moves {}", moves.join(" ")).unwrap();
+                    write!(buf, " moves {}", moves.join(" ")).unwrap();
                 }
+                writeln!(buf).unwrap();
             }
         }
         buf
@@ -117,3 +479,93 @@ impl Display for UciPosition {
         Ok(())
     }
 }
+
+impl Display for GoParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let times = [
+            ("wtime", self.wtime),
+            ("btime", self.btime),
+            ("winc", self.winc),
+            ("binc", self.binc),
+            ("movetime", self.movetime),
+        ];
+        for (keyword, time) in times {
+            if let Some(time) = time {
+                write!(f, " {} {}", keyword, time.as_millis())?;
+            }
+        }
+        if let Some(movestogo) = self.movestogo {
+            write!(f, " movestogo {}", movestogo)?;
+        }
+        if let Some(depth) = self.depth {
+            write!(f, " depth {}", depth)?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {}", nodes)?;
+        }
+        if let Some(mate) = self.mate {
+            write!(f, " mate {}", mate)?;
+        }
+        if self.infinite {
+            write!(f, " infinite")?;
+        }
+        if self.ponder {
+            write!(f, " ponder")?;
+        }
+        if !self.searchmoves.is_empty() {
+            write!(f, " searchmoves {}", self.searchmoves.join(" "))?;
+        }
+        if let Some(perft) = self.perft {
+            write!(f, " perft {}", perft)?;
+        }
+        Ok(())
+    }
+}
+
+// SearchInfo lives in the engine, which knows nothing about UCI
+struct DisplayInfo<'a>(&'a SearchInfo);
+
+impl Display for DisplayInfo<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let info = self.0;
+        if let Some(depth) = info.depth {
+            write!(f, " depth {}", depth)?;
+        }
+        if let Some(seldepth) = info.seldepth {
+            write!(f, " seldepth {}", seldepth)?;
+        }
+        if let Some(multipv) = info.multipv {
+            write!(f, " multipv {}", multipv)?;
+        }
+        match info.score {
+            Some(InfoScore::Centipawns(cp)) => write!(f, " score cp {}", cp)?,
+            Some(InfoScore::Mate(moves)) => write!(f, " score mate {}", moves)?,
+            None => {}
+        }
+        if let Some(nodes) = info.nodes {
+            write!(f, " nodes {}", nodes)?;
+        }
+        if let Some(nps) = info.nps {
+            write!(f, " nps {}", nps)?;
+        }
+        if let Some(hashfull) = info.hashfull {
+            write!(f, " hashfull {}", hashfull)?;
+        }
+        if let Some(time) = info.time {
+            write!(f, " time {}", time.as_millis())?;
+        }
+        if let Some(currmove) = info.currmove {
+            write!(f, " currmove {}", currmove.into_long_algebraic())?;
+        }
+        if let Some(currmovenumber) = info.currmovenumber {
+            write!(f, " currmovenumber {}", currmovenumber)?;
+        }
+        if !info.pv.is_empty() {
+            write!(f, " pv")?;
+            for mov in &info.pv {
+                write!(f, " {}", mov.into_long_algebraic())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{
+    board::SearchBoard,
+    engine::{Bot, config::SearchConfig, evaluate::Outcome, search_limits::SearchLimits},
+    moving::Move,
+    piece::Side,
+    uci::{
+        client::UciEngineProcess,
+        command::{GoParams, UciPosition},
+        options::CHESS960,
+        session::ENGINE_NAME,
+    },
+};
+
+/// PGN lines are kept below this many characters, as the export format asks
+const PGN_LINE_WIDTH: usize = 79;
+
+#[derive(Clone, Debug)]
+pub struct MatchConfig {
+    pub games: u32,
+    /// Depth our own bot searches every move to
+    pub bot_depth: i32,
+    /// Sent with every `go` to the opponent
+    pub opponent_limits: GoParams,
+    /// An opponent taking longer than this for a move is considered hung, which ends the match
+    pub move_timeout: Duration,
+    /// Games that get this long are adjudicated as draws
+    pub max_plies: usize,
+    pub event: String,
+    /// Every pair of games starts from a random Chess960 position, once with each color
+    pub chess960: bool,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            games: 2,
+            bot_depth: 4,
+            opponent_limits: GoParams {
+                movetime: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+            move_timeout: Duration::from_secs(30),
+            max_plies: 300,
+            event: "chess_engine match".to_string(),
+            chess960: false,
+        }
+    }
+}
+
+/// A finished game
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub white: String,
+    pub black: String,
+    /// `None` for the usual starting position
+    pub start_fen: Option<String>,
+    pub chess960: bool,
+    pub moves: Vec<Move>,
+    pub outcome: Outcome,
+    /// Why the game ended, if it wasn't over the board
+    pub termination: Option<&'static str>,
+}
+
+impl GameRecord {
+    pub fn result(&self) -> &'static str {
+        match self.outcome {
+            Outcome::WhiteWon => "1-0",
+            Outcome::BlackWon => "0-1",
+            Outcome::Stalemate => "1/2-1/2",
+            Outcome::Ongoing => "*",
+        }
+    }
+
+    pub fn to_pgn(&self, event: &str, round: u32) -> String {
+        let mut pgn = String::new();
+        writeln!(pgn, "[Event \"{}\"]", event).unwrap();
+        writeln!(pgn, "[Site \"?\"]").unwrap();
+        writeln!(pgn, "[Date \"????.??.??\"]").unwrap();
+        writeln!(pgn, "[Round \"{}\"]", round).unwrap();
+        writeln!(pgn, "[White \"{}\"]", self.white).unwrap();
+        writeln!(pgn, "[Black \"{}\"]", self.black).unwrap();
+        writeln!(pgn, "[Result \"{}\"]", self.result()).unwrap();
+        if let Some(termination) = self.termination {
+            writeln!(pgn, "[Termination \"{}\"]", termination).unwrap();
+        }
+        if self.chess960 {
+            writeln!(pgn, "[Variant \"Chess960\"]").unwrap();
+        }
+        if let Some(fen) = &self.start_fen {
+            writeln!(pgn, "[SetUp \"1\"]").unwrap();
+            writeln!(pgn, "[FEN \"{}\"]", fen).unwrap();
+        }
+        writeln!(pgn).unwrap();
+
+        let mut board = self
+            .start_fen
+            .as_deref()
+            .map_or_else(SearchBoard::default, SearchBoard::from_fen);
+        let mut tokens = Vec::with_capacity(self.moves.len() * 3 / 2 + 1);
+        for (ply, mov) in self.moves.iter().enumerate() {
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            }
+            tokens.push(mov.into_san(&board));
+            board.make(mov);
+        }
+        tokens.push(self.result().to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > PGN_LINE_WIDTH {
+                writeln!(pgn, "{}", line).unwrap();
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(pgn, "{}", line).unwrap();
+        pgn
+    }
+}
+
+/// Games won, lost and drawn by our bot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+/// Plays our `Bot` against an external UCI engine, alternating colors every game
+pub struct MatchRunner {
+    config: MatchConfig,
+    engine: UciEngineProcess,
+    bot: Bot,
+}
+
+impl MatchRunner {
+    pub fn new(engine: UciEngineProcess, config: MatchConfig) -> Self {
+        let mut bot = Bot::default();
+        bot.set_config(SearchConfig {
+            chess960: config.chess960,
+            ..Default::default()
+        });
+        Self {
+            config,
+            engine,
+            bot,
+        }
+    }
+
+    /// Plays every game of the match, writing each one to `pgn` as soon as it is over
+    pub fn run(&mut self, pgn: &mut impl Write) -> io::Result<MatchScore> {
+        let mut score = MatchScore::default();
+        if self.config.chess960 {
+            self.engine.set_option(CHESS960, Some("true"))?;
+        }
+        let mut start_fen = None;
+        for round in 1..=self.config.games {
+            let bot_side = if round % 2 == 1 {
+                Side::White
+            } else {
+                Side::Black
+            };
+            if self.config.chess960 && bot_side == Side::White {
+                let board = SearchBoard::chess960_start(rand::random_range(0..960)).unwrap();
+                start_fen = Some(board.to_fen());
+            }
+            let game = self.play_game(bot_side, start_fen.as_deref())?;
+            match (game.outcome, bot_side) {
+                (Outcome::WhiteWon, Side::White) | (Outcome::BlackWon, Side::Black) => {
+                    score.wins += 1
+                }
+                (Outcome::WhiteWon, Side::Black) | (Outcome::BlackWon, Side::White) => {
+                    score.losses += 1
+                }
+                _ => score.draws += 1,
+            }
+            writeln!(pgn, "{}", game.to_pgn(&self.config.event, round))?;
+            pgn.flush()?;
+        }
+        Ok(score)
+    }
+
+    /// Plays one game from `start_fen`, or from the usual starting position if it is `None`
+    pub fn play_game(&mut self, bot_side: Side, start_fen: Option<&str>) -> io::Result<GameRecord> {
+        let bot_name = ENGINE_NAME.to_string();
+        let engine_name = match self.engine.name() {
+            "" => "?".to_string(),
+            name => name.to_string(),
+        };
+        let (white, black) = match bot_side {
+            Side::White => (bot_name, engine_name),
+            Side::Black => (engine_name, bot_name),
+        };
+        let mut record = GameRecord {
+            white,
+            black,
+            start_fen: start_fen.map(str::to_string),
+            chess960: self.config.chess960,
+            moves: Vec::new(),
+            outcome: Outcome::Ongoing,
+            termination: None,
+        };
+
+        match start_fen {
+            Some(fen) if self.bot.load_fen(fen).is_none() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid FEN '{}'", fen),
+                ));
+            }
+            Some(_) => {}
+            None => self.bot.load_startpos(),
+        }
+        self.bot.clear_hash();
+        self.engine.new_game()?;
+        let mut long_algebraic = Vec::new();
+        while !self.bot.outcome().is_game_over() {
+            if record.moves.len() >= self.config.max_plies {
+                record.outcome = Outcome::Stalemate;
+                record.termination = Some("adjudication");
+                return Ok(record);
+            }
+            let side = self.bot.get_board().side();
+            let mov = if side == bot_side {
+                self.bot
+                    .find_best_move(SearchLimits::depth(self.config.bot_depth))
+                    .map(|(mov, _)| mov)
+            } else {
+                self.engine_move(start_fen, &long_algebraic)?
+            };
+            // the bot always has a move while the game is on, so this is the opponent's fault
+            let Some(mov) = mov else {
+                record.outcome = match side {
+                    Side::White => Outcome::BlackWon,
+                    Side::Black => Outcome::WhiteWon,
+                };
+                record.termination = Some("rules infraction");
+                return Ok(record);
+            };
+            self.bot.make_move(&mov);
+            long_algebraic.push(mov.into_long_algebraic());
+            record.moves.push(mov);
+        }
+        record.outcome = self.bot.outcome();
+        Ok(record)
+    }
+
+    /// The opponent's move, `None` if it is missing or illegal
+    fn engine_move(
+        &mut self,
+        start_fen: Option<&str>,
+        moves: &[String],
+    ) -> io::Result<Option<Move>> {
+        let position = match start_fen {
+            Some(fen) => UciPosition::Fen(fen.to_string()),
+            None => UciPosition::StartPos,
+        };
+        self.engine.set_position(position, moves)?;
+        self.engine.start_search(&self.config.opponent_limits)?;
+        let search = self
+            .engine
+            .wait_for_best_move(Some(self.config.move_timeout))?;
+        Ok(search.best_move.and_then(|mov| self.bot.parse_move(&mov)))
+    }
+
+    pub fn into_engine(self) -> UciEngineProcess {
+        self.engine
+    }
+}
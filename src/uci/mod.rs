@@ -1,3 +1,6 @@
+pub mod client;
 pub mod command;
+pub mod match_runner;
+pub mod options;
 pub mod session;
 pub mod stream;
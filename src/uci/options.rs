@@ -0,0 +1,274 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::{
+    engine::config::{Pruning, SearchConfig},
+    uci::command::{Tokens, UciCommand, UciParseError},
+};
+
+pub const HASH: &str = "Hash";
+pub const CLEAR_HASH: &str = "Clear Hash";
+pub const THREADS: &str = "Threads";
+pub const MULTI_PV: &str = "MultiPV";
+pub const MOVE_OVERHEAD: &str = "Move Overhead";
+pub const QUIESCENCE_CHECKS: &str = "Quiescence Checks";
+pub const CONTEMPT: &str = "Contempt";
+pub const PONDER: &str = "Ponder";
+pub const CHESS960: &str = "UCI_Chess960";
+pub const NULL_MOVE_PRUNING: &str = "Null Move Pruning";
+pub const LATE_MOVE_REDUCTIONS: &str = "Late Move Reductions";
+pub const REVERSE_FUTILITY_PRUNING: &str = "Reverse Futility Pruning";
+pub const FUTILITY_PRUNING: &str = "Futility Pruning";
+pub const RAZORING: &str = "Razoring";
+pub const LATE_MOVE_PRUNING: &str = "Late Move Pruning";
+pub const SEE_PRUNING: &str = "SEE Pruning";
+pub const DELTA_PRUNING: &str = "Delta Pruning";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciOptionKind {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    Combo { default: String, vars: Vec<String> },
+    Button,
+    String { default: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciOptionValue {
+    Check(bool),
+    Spin(i64),
+    Combo(String),
+    /// buttons have no state, pressing one is an event
+    Button,
+    String(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: UciOptionKind,
+    pub value: UciOptionValue,
+}
+
+impl UciOption {
+    pub fn new(name: &'static str, kind: UciOptionKind) -> Self {
+        let value = match &kind {
+            UciOptionKind::Check { default } => UciOptionValue::Check(*default),
+            UciOptionKind::Spin { default, .. } => UciOptionValue::Spin(*default),
+            UciOptionKind::Combo { default, .. } => UciOptionValue::Combo(default.clone()),
+            UciOptionKind::Button => UciOptionValue::Button,
+            UciOptionKind::String { default } => UciOptionValue::String(default.clone()),
+        };
+        Self { name, kind, value }
+    }
+
+    fn parse_value(&self, value: Option<&str>) -> Result<UciOptionValue, String> {
+        let value = match (&self.kind, value) {
+            (UciOptionKind::Button, _) => return Ok(UciOptionValue::Button),
+            (_, Some(value)) => value,
+            (_, None) => return Err(format!("option {} needs a value", self.name)),
+        };
+        match &self.kind {
+            UciOptionKind::Check { .. } => match value {
+                "true" => Ok(UciOptionValue::Check(true)),
+                "false" => Ok(UciOptionValue::Check(false)),
+                _ => Err(format!("{} is not true or false", value)),
+            },
+            UciOptionKind::Spin { min, max, .. } => match value.parse::<i64>() {
+                Ok(spin) if (*min..=*max).contains(&spin) => Ok(UciOptionValue::Spin(spin)),
+                _ => Err(format!(
+                    "{} is not a number between {} and {}",
+                    value, min, max
+                )),
+            },
+            UciOptionKind::Combo { vars, .. } => vars
+                .iter()
+                .find(|var| var.eq_ignore_ascii_case(value))
+                .map(|var| UciOptionValue::Combo(var.clone()))
+                .ok_or_else(|| format!("{} is not one of {}", value, vars.join(", "))),
+            UciOptionKind::String { .. } => Ok(UciOptionValue::String(value.to_string())),
+            UciOptionKind::Button => unreachable!(),
+        }
+    }
+}
+
+/// Every option the engine advertises after `uci`, with their current values
+pub struct UciOptions {
+    options: Vec<UciOption>,
+}
+
+impl UciOptions {
+    pub fn new() -> Self {
+        let defaults = SearchConfig::default();
+        let spin = |default: i64, min: i64, max: i64| UciOptionKind::Spin { default, min, max };
+        let check = |default: bool| UciOptionKind::Check { default };
+        let pruning = defaults.pruning;
+        Self {
+            options: vec![
+                UciOption::new(HASH, spin(defaults.hash_size_mb as i64, 1, 65536)),
+                UciOption::new(CLEAR_HASH, UciOptionKind::Button),
+                UciOption::new(THREADS, spin(defaults.threads as i64, 1, 256)),
+                UciOption::new(MULTI_PV, spin(defaults.multi_pv as i64, 1, 256)),
+                UciOption::new(
+                    MOVE_OVERHEAD,
+                    spin(defaults.move_overhead.as_millis() as i64, 0, 5000),
+                ),
+                UciOption::new(QUIESCENCE_CHECKS, check(defaults.quiescence_checks)),
+                UciOption::new(CONTEMPT, spin(defaults.contempt, -1000, 1000)),
+                UciOption::new(PONDER, UciOptionKind::Check { default: false }),
+                UciOption::new(
+                    CHESS960,
+                    UciOptionKind::Check {
+                        default: defaults.chess960,
+                    },
+                ),
+                UciOption::new(NULL_MOVE_PRUNING, check(pruning.null_move)),
+                UciOption::new(LATE_MOVE_REDUCTIONS, check(pruning.late_move_reductions)),
+                UciOption::new(REVERSE_FUTILITY_PRUNING, check(pruning.reverse_futility)),
+                UciOption::new(FUTILITY_PRUNING, check(pruning.futility)),
+                UciOption::new(RAZORING, check(pruning.razoring)),
+                UciOption::new(LATE_MOVE_PRUNING, check(pruning.late_move_pruning)),
+                UciOption::new(SEE_PRUNING, check(pruning.see)),
+                UciOption::new(DELTA_PRUNING, check(pruning.delta)),
+            ],
+        }
+    }
+
+    /// The `option` lines sent in reply to `uci`
+    pub fn declarations(&self) -> impl Iterator<Item = UciCommand> + '_ {
+        self.options.iter().map(|option| UciCommand::Option {
+            name: option.name.to_string(),
+            kind: option.kind.clone(),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&UciOption> {
+        // option names are case insensitive
+        self.options
+            .iter()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Updates the option `name`, returns the option on success
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<&UciOption, String> {
+        let option = self
+            .options
+            .iter_mut()
+            .find(|option| option.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no such option: {}", name))?;
+        option.value = option.parse_value(value)?;
+        Ok(option)
+    }
+
+    pub fn spin(&self, name: &str) -> i64 {
+        match self.get(name).map(|option| &option.value) {
+            Some(UciOptionValue::Spin(value)) => *value,
+            _ => panic!("{} is not a spin option", name),
+        }
+    }
+
+    pub fn check(&self, name: &str) -> bool {
+        match self.get(name).map(|option| &option.value) {
+            Some(UciOptionValue::Check(value)) => *value,
+            _ => panic!("{} is not a check option", name),
+        }
+    }
+
+    pub fn search_config(&self) -> SearchConfig {
+        SearchConfig {
+            hash_size_mb: self.spin(HASH) as usize,
+            threads: self.spin(THREADS) as usize,
+            multi_pv: self.spin(MULTI_PV) as usize,
+            move_overhead: Duration::from_millis(self.spin(MOVE_OVERHEAD) as u64),
+            quiescence_checks: self.check(QUIESCENCE_CHECKS),
+            contempt: self.spin(CONTEMPT),
+            chess960: self.check(CHESS960),
+            pruning: Pruning {
+                null_move: self.check(NULL_MOVE_PRUNING),
+                late_move_reductions: self.check(LATE_MOVE_REDUCTIONS),
+                reverse_futility: self.check(REVERSE_FUTILITY_PRUNING),
+                futility: self.check(FUTILITY_PRUNING),
+                razoring: self.check(RAZORING),
+                late_move_pruning: self.check(LATE_MOVE_PRUNING),
+                see: self.check(SEE_PRUNING),
+                delta: self.check(DELTA_PRUNING),
+            },
+        }
+    }
+}
+
+impl Default for UciOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UciOptionKind {
+    /// Parses what follows `type` in an `option` declaration, e.g. `spin default 1 min 1 max 8`
+    pub(super) fn parse(tokens: &mut Tokens) -> Result<Self, UciParseError> {
+        const FIELDS: [&str; 4] = ["default", "min", "max", "var"];
+        let kind = tokens.expect("option type")?;
+        let (mut default, mut min, mut max, mut vars) = (None, None, None, Vec::new());
+        while let Some(field) = tokens.next() {
+            let value = tokens.words_until_any(&FIELDS);
+            match field {
+                "default" => default = Some(value),
+                "min" => min = Some(value),
+                "max" => max = Some(value),
+                "var" => vars.push(value),
+                token => return Err(tokens.invalid(token)),
+            }
+        }
+        let spin = |value: Option<String>, expected| {
+            let value = value.ok_or_else(|| tokens.missing(expected))?;
+            value.parse().map_err(|_| tokens.invalid(&value))
+        };
+        match kind {
+            "check" => match default.as_deref() {
+                Some("true") => Ok(UciOptionKind::Check { default: true }),
+                Some("false") => Ok(UciOptionKind::Check { default: false }),
+                Some(value) => Err(tokens.invalid(value)),
+                None => Err(tokens.missing("default")),
+            },
+            "spin" => Ok(UciOptionKind::Spin {
+                default: spin(default, "default")?,
+                min: spin(min, "min")?,
+                max: spin(max, "max")?,
+            }),
+            "combo" => Ok(UciOptionKind::Combo {
+                default: default.ok_or_else(|| tokens.missing("default"))?,
+                vars,
+            }),
+            "button" => Ok(UciOptionKind::Button),
+            "string" => Ok(UciOptionKind::String {
+                default: default
+                    .filter(|default| default != "<empty>")
+                    .unwrap_or_default(),
+            }),
+            token => Err(tokens.invalid(token)),
+        }
+    }
+}
+
+impl Display for UciOptionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UciOptionKind::Check { default } => write!(f, "check default {}", default),
+            UciOptionKind::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            UciOptionKind::Combo { default, vars } => {
+                write!(f, "combo default {}", default)?;
+                for var in vars {
+                    write!(f, " var {}", var)?;
+                }
+                Ok(())
+            }
+            UciOptionKind::Button => write!(f, "button"),
+            // the spec has no way of sending an empty string, the convention is <empty>
+            UciOptionKind::String { default } if default.is_empty() => {
+                write!(f, "string default <empty>")
+            }
+            UciOptionKind::String { default } => write!(f, "string default {}", default),
+        }
+    }
+}
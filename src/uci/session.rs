@@ -1,18 +1,339 @@
-use crate::{engine::Bot, uci::stream::UciStream};
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{Stdout, Write},
+    time::Instant,
+};
 
-pub struct UciSession {
-    stream: UciStream,
-    game: Option<Bot>,
+use crate::{
+    board_repr::write_board,
+    engine::{
+        Bot,
+        evaluate::eval_to_centipawns,
+        search_info::{SearchResult, nodes_per_second},
+        search_limits::SearchLimits,
+    },
+    uci::{
+        command::{GoParams, UciCommand, UciPosition},
+        options::{self, UciOptions},
+        stream::UciStream,
+    },
+};
+
+/// Searched by `bench`, the perft test positions
+const BENCH_POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+const BENCH_DEPTH: u32 = 4;
+
+pub(crate) const ENGINE_NAME: &str = concat!("chess_engine ", env!("CARGO_PKG_VERSION"));
+const ENGINE_AUTHOR: &str = "Olah Balazs";
+
+/// Depth used when `go` doesn't limit the search at all
+const DEFAULT_DEPTH: i32 = 5;
+
+pub struct UciSession<W: Write = Stdout> {
+    stream: UciStream<W>,
+    // kept for the whole session, so the hash table survives between positions
+    game: Bot,
+    options: UciOptions,
+    // commands that arrived before the search they came after started
+    pending: VecDeque<UciCommand>,
 
     uci_received: bool,
 }
 
-impl UciSession {
-    pub fn new(stream: UciStream) -> Self {
+impl<W: Write + Send + 'static> UciSession<W> {
+    pub fn new(stream: UciStream<W>) -> Self {
+        let options = UciOptions::new();
+        let mut game = Bot::default();
+        game.set_config(options.search_config());
         Self {
             stream,
-            game: None,
+            game,
+            options,
+            pending: VecDeque::new(),
             uci_received: false,
         }
     }
+
+    pub fn into_stream(self) -> UciStream<W> {
+        self.stream
+    }
+
+    /// Handles commands until the GUI sends `quit` or closes the stream
+    pub fn run(&mut self) {
+        while let Some(command) = self.next_command() {
+            match command {
+                UciCommand::Uci => {
+                    self.uci_received = true;
+                    self.stream.send(UciCommand::Id {
+                        name: ENGINE_NAME.to_string(),
+                        author: ENGINE_AUTHOR.to_string(),
+                    });
+                    for option in self.options.declarations() {
+                        self.stream.send(option);
+                    }
+                    self.stream.send(UciCommand::UciOk);
+                }
+                UciCommand::IsReady => self.stream.send(UciCommand::ReadyOk),
+                UciCommand::SetOption { name, value } => self.set_option(&name, value.as_deref()),
+                UciCommand::UciNewGame => {
+                    self.game.clear_hash();
+                    self.game.load_startpos();
+                }
+                UciCommand::Position { position, moves } => self.setup_position(&position, &moves),
+                UciCommand::Go(params) if params.perft.is_some() => self.perft(&params),
+                UciCommand::Go(params) if !self.go(&params) => break,
+                UciCommand::ShowBoard => self.show_board(),
+                UciCommand::Eval => self.eval(),
+                UciCommand::Bench { depth } => self.bench(depth.unwrap_or(BENCH_DEPTH)),
+                UciCommand::Quit => break,
+                _ => {}
+            }
+        }
+        self.stream.shutdown();
+    }
+
+    /// Searches and sends `bestmove`. Returns false if `quit` arrived in the meantime.
+    fn go(&mut self, params: &GoParams) -> bool {
+        self.start_search(params);
+        let stream = &self.stream;
+        let game = &mut self.game;
+        game.set_stop_token(stream.stop_token());
+
+        let limits = search_limits(params, game);
+        let result =
+            game.find_best_moves_reporting(limits, |info| stream.send(UciCommand::Info(info)));
+        stream.set_searching(false);
+        let best_move = result.as_ref().map(|result| result.best_move);
+        // the reply we expect is what the GUI should ponder on
+        let ponder = result.as_ref().and_then(SearchResult::ponder_move);
+
+        // infinite and pondering searches may only report after the GUI said so
+        let mut keep_running = true;
+        let stop_token = stream.stop_token();
+        if !stop_token.is_stopped() && (params.infinite || stop_token.is_pondering()) {
+            keep_running = self.wait_for_stop();
+        }
+        self.stream.send(UciCommand::BestMove {
+            mov: best_move,
+            ponder,
+        });
+        keep_running
+    }
+
+    /// Readies the stop token for a search. A `stop`, `quit` or `ponderhit` that is already
+    /// queued reached the token before the reset, so it is applied again, up to the next `go`,
+    /// and so is the end of input for a search that can't finish on its own. From here on the
+    /// listener answers `isready`, the ones already queued are answered now.
+    fn start_search(&mut self, params: &GoParams) {
+        self.stream.set_searching(true);
+        let stop_token = self.stream.stop_token();
+        stop_token.reset(params.ponder);
+        if !self.stream.is_listening() && (params.infinite || params.ponder) {
+            stop_token.stop();
+        }
+        let mut next_search = false;
+        while let Some(command) = self.stream.try_read() {
+            match command {
+                UciCommand::IsReady => {
+                    self.stream.send(UciCommand::ReadyOk);
+                    continue;
+                }
+                UciCommand::Go(_) => next_search = true,
+                _ if next_search => {}
+                UciCommand::Stop => {
+                    stop_token.stop();
+                    continue;
+                }
+                UciCommand::Quit => stop_token.stop(),
+                UciCommand::PonderHit => {
+                    stop_token.ponderhit();
+                    continue;
+                }
+                _ => {}
+            }
+            self.pending.push_back(command);
+        }
+    }
+
+    fn next_command(&mut self) -> Option<UciCommand> {
+        self.pending.pop_front().or_else(|| self.stream.read())
+    }
+
+    /// Waits for `stop` or `ponderhit`, returns false on `quit`
+    fn wait_for_stop(&mut self) -> bool {
+        while let Some(command) = self.next_command() {
+            match command {
+                UciCommand::Stop | UciCommand::PonderHit => return true,
+                UciCommand::Quit => return false,
+                UciCommand::IsReady => self.stream.send(UciCommand::ReadyOk),
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn perft(&self, params: &GoParams) {
+        let depth = params.perft.unwrap_or(1);
+        let mut text = String::new();
+        let mut total = 0;
+        for (mov, nodes) in self.game.perft_divide(depth) {
+            writeln!(text, "{}: {}", mov.into_long_algebraic(), nodes).unwrap();
+            total += nodes;
+        }
+        writeln!(text, "\nNodes searched: {}", total).unwrap();
+        self.stream.send(UciCommand::Raw(text));
+    }
+
+    fn show_board(&self) {
+        let board = self.game.get_board();
+        let mut drawing = Vec::new();
+        write_board(&mut drawing, &board.board).unwrap();
+        let mut text = String::from_utf8_lossy(&drawing).into_owned();
+        writeln!(text, "\nFen: {}", board.to_fen()).unwrap();
+        writeln!(text, "Key: {:016X}", board.zobrist).unwrap();
+        self.stream.send(UciCommand::Raw(text));
+    }
+
+    fn eval(&self) {
+        let breakdown = self.game.eval_breakdown();
+        let pawns = |eval| eval_to_centipawns(eval) as f64 / 100.0;
+        let mut text = String::new();
+        if breakdown.outcome.is_some() {
+            writeln!(text, "Game over, the other terms don't count").unwrap();
+        }
+        let terms = [
+            ("Material", breakdown.material, "white side"),
+            ("Positional", breakdown.positional, "white side"),
+            ("Mobility", breakdown.mobility, "side to move"),
+            ("Check", breakdown.check, "side to move"),
+        ];
+        for (term, eval, view) in terms {
+            writeln!(text, "{:<12}{:>+8.2} ({})", term, pawns(eval), view).unwrap();
+        }
+        let total = breakdown.total();
+        if total.is_mate() {
+            writeln!(
+                text,
+                "{:<12}{:>8} (side to move)",
+                "Total",
+                total.to_string()
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                text,
+                "{:<12}{:>+8.2} (side to move)",
+                "Total",
+                pawns(total.value())
+            )
+            .unwrap();
+        }
+        self.stream.send(UciCommand::Raw(text));
+    }
+
+    /// Searches every bench position with a cold hash table, without touching the game
+    fn bench(&self, depth: u32) {
+        let mut bot = Bot::default();
+        bot.set_config(self.game.config().clone());
+        let start = Instant::now();
+        let mut total_nodes = 0;
+        for (index, fen) in BENCH_POSITIONS.iter().enumerate() {
+            bot.clear_hash();
+            bot.load_fen(fen).expect("bench positions are valid");
+            let mut nodes = 0;
+            bot.find_best_moves_reporting(SearchLimits::depth(depth as i32), |info| {
+                nodes = info.nodes.unwrap_or(nodes);
+            });
+            total_nodes += nodes;
+            self.stream.send(UciCommand::Raw(format!(
+                "Position {}/{}: {} nodes",
+                index + 1,
+                BENCH_POSITIONS.len(),
+                nodes
+            )));
+        }
+        let elapsed = start.elapsed();
+        let mut text = String::new();
+        writeln!(text, "\nTotal time (ms) : {}", elapsed.as_millis()).unwrap();
+        writeln!(text, "Nodes searched  : {}", total_nodes).unwrap();
+        writeln!(
+            text,
+            "Nodes/second    : {}",
+            nodes_per_second(total_nodes, elapsed)
+        )
+        .unwrap();
+        self.stream.send(UciCommand::Raw(text));
+    }
+
+    fn set_option(&mut self, name: &str, value: Option<&str>) {
+        match self.options.set(name, value) {
+            Ok(option) if option.name == options::CLEAR_HASH => self.game.clear_hash(),
+            Ok(_) => self.game.set_config(self.options.search_config()),
+            Err(reason) => self.stream.send(UciCommand::InfoString(reason)),
+        }
+    }
+
+    fn setup_position(&mut self, position: &UciPosition, moves: &[String]) {
+        match position {
+            UciPosition::StartPos => self.game.load_startpos(),
+            UciPosition::Fen(fen) => {
+                if self.game.load_fen(fen).is_none() {
+                    self.stream.send(UciCommand::InfoString(format!(
+                        "position: invalid FEN '{}'",
+                        fen
+                    )));
+                    return;
+                }
+            }
+        }
+        for mov in moves {
+            // an illegal move leaves the position where it was, there is nothing better to do
+            let Some(parsed) = self.game.parse_move(mov) else {
+                self.stream.send(UciCommand::InfoString(format!(
+                    "position: illegal move '{}'",
+                    mov
+                )));
+                break;
+            };
+            self.game.make_move(&parsed);
+        }
+    }
+}
+
+/// What `go` limits the search of `game` to, `DEFAULT_DEPTH` if it doesn't limit it at all
+pub(crate) fn search_limits(params: &GoParams, game: &Bot) -> SearchLimits {
+    let side = game.get_board().side();
+    let limits = SearchLimits {
+        depth: params.depth.map(|depth| depth.max(1) as i32),
+        nodes: params.nodes,
+        move_time: params.movetime,
+        time: params.time_manager(side, game.config().move_overhead),
+        mate: params.mate,
+        infinite: params.infinite,
+        // illegal moves are dropped, if none is left every move is searched
+        search_moves: params
+            .searchmoves
+            .iter()
+            .filter_map(|mov| game.parse_move(mov))
+            .collect(),
+        excluded_moves: Vec::new(),
+    };
+    let unlimited = limits.depth.is_none()
+        && limits.nodes.is_none()
+        && limits.move_time.is_none()
+        && limits.time.is_none()
+        && limits.mate.is_none()
+        && !limits.infinite;
+    if unlimited {
+        return limits.with_depth(DEFAULT_DEPTH);
+    }
+    limits
 }
@@ -1,57 +1,68 @@
-use std::{
-    collections::VecDeque,
-    io::{BufRead, BufReader, Write, stdin},
-    sync::{Arc, Mutex},
-    thread,
+use std::io::{self, Stdout, Write};
+
+use crate::{
+    line_stream::{Interrupt, LineStream, Protocol},
+    uci::command::{UciCommand, UciParseError},
 };
 
-use crate::uci::command::UciCommand;
+/// Both ends of a UCI conversation. Lines that don't parse are answered with an `info string`
+/// explaining why.
+pub type UciStream<W = Stdout> = LineStream<Uci, W>;
 
-pub struct UciStream {
-    inner: Arc<Mutex<VecDeque<UciCommand>>>,
-    stdout: std::io::Stdout,
-    stop_signal: Arc<Mutex<bool>>,
+#[derive(Default)]
+pub struct Uci {
+    // only an infinite or pondering search can't finish on its own
+    unbounded_search: bool,
 }
 
-impl UciStream {
-    pub fn new() -> Self {
-        let stream = Self {
-            inner: Arc::new(Mutex::new(VecDeque::new())),
-            stdout: std::io::stdout(),
-            stop_signal: Arc::new(Mutex::new(false)),
-        };
-        stream.start_listener();
+impl Protocol for Uci {
+    type Command = UciCommand;
+    type ParseError = UciParseError;
+    type Reply = UciCommand;
 
-        stream
+    fn parse(line: &str) -> Option<Result<UciCommand, UciParseError>> {
+        match UciCommand::parse(line) {
+            Err(UciParseError::Empty) => None,
+            command => Some(command),
+        }
     }
 
-    pub fn send(&self, command: UciCommand) {
-        let mut stdout = self.stdout.lock();
-        writeln!(stdout, "{}", command.to_string()).ok();
+    fn error_reply(err: UciParseError) -> UciCommand {
+        UciCommand::InfoString(err.to_string())
     }
 
-    pub fn try_read(&self) -> Option<UciCommand> {
-        let mut queue = self.inner.lock().unwrap();
-        queue.pop_front()
+    fn write_reply(writer: &mut impl Write, reply: &UciCommand) -> io::Result<()> {
+        writer.write_all(reply.to_string().as_bytes())
     }
 
-    pub fn is_listening(&self) -> bool {
-        !*self.stop_signal.lock().unwrap()
+    fn is_quit(command: &UciCommand) -> bool {
+        matches!(command, UciCommand::Quit)
     }
 
-    fn start_listener(&self) {
-        let queue = Arc::clone(&self.inner);
-        let stop_signal = Arc::clone(&self.stop_signal);
-        thread::spawn(move || {
-            let mut stdin = BufReader::new(stdin());
-            while !*stop_signal.lock().unwrap() {
-                let mut buf = String::new();
-                stdin.read_line(&mut buf).ok();
-
-                if let Some(command) = UciCommand::parse(&buf) {
-                    queue.lock().unwrap().push_back(command);
-                }
+    /// `stop` and `quit` stop the search, `ponderhit` lets it keep the time it took
+    fn interrupt(&mut self, command: &UciCommand) -> Interrupt {
+        match command {
+            UciCommand::Go(params) => {
+                self.unbounded_search = params.infinite || params.ponder;
+                Interrupt::None
             }
-        });
+            UciCommand::PonderHit => Interrupt::PonderHit,
+            UciCommand::Stop | UciCommand::Quit => Interrupt::Stop,
+            _ => Interrupt::None,
+        }
+    }
+
+    /// A search that was already queued still completes, so piped scripts get their answer
+    fn end_of_input(&mut self) -> Interrupt {
+        if self.unbounded_search {
+            Interrupt::Stop
+        } else {
+            Interrupt::None
+        }
+    }
+
+    /// A GUI asks `isready` to make sure the engine is alive, a search must not delay it
+    fn busy_reply(command: &UciCommand) -> Option<UciCommand> {
+        matches!(command, UciCommand::IsReady).then_some(UciCommand::ReadyOk)
     }
 }
@@ -52,7 +52,7 @@ impl ZobristRandom {
             hash ^= self.white_castle_rights[1]
         }
         if state.black_castling.0 {
-            hash ^= self.black_castle_rights[1]
+            hash ^= self.black_castle_rights[0]
         }
         if state.black_castling.1 {
             hash ^= self.black_castle_rights[1]